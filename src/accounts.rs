@@ -0,0 +1,197 @@
+use hmac::Hmac;
+use pbkdf2::pbkdf2;
+use persistence::write_json;
+use rand::{self, Rng};
+use serde_json;
+use sha2::Sha256;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+pub const DEFAULT_ACCOUNTS_PATH: &str = "saves/accounts.json";
+
+const HASH_ROUNDS: usize = 10_000;
+const SALT_LEN: usize = 16;
+const HASH_LEN: usize = 32;
+
+// Players send this to the server before their character to log in, or once
+// playing to change their password.
+pub fn parse_password_command(text: &str) -> Option<String> {
+    let password = text.trim().strip_prefix("/password ")?.trim();
+    if password.is_empty() {
+        None
+    } else {
+        Some(password.to_string())
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct Credentials {
+    salt: String,
+    hash: String,
+}
+
+impl Credentials {
+    fn new(password: &str) -> Credentials {
+        let mut salt = [0u8; SALT_LEN];
+        rand::thread_rng().fill(&mut salt);
+
+        Credentials {
+            salt: to_hex(&salt),
+            hash: to_hex(&hash_password(password, &salt)),
+        }
+    }
+
+    fn matches(&self, password: &str) -> bool {
+        let salt = match from_hex(&self.salt) {
+            Some(salt) => salt,
+            None => return false,
+        };
+        let expected = match from_hex(&self.hash) {
+            Some(hash) => hash,
+            None => return false,
+        };
+
+        constant_time_eq(&hash_password(password, &salt), &expected)
+    }
+}
+
+// Compares in full every time so the timing doesn't give the secret away.
+pub fn constant_time_eq(actual: &[u8], expected: &[u8]) -> bool {
+    actual.len() == expected.len()
+        && actual
+            .iter()
+            .zip(expected.iter())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+fn hash_password(password: &str, salt: &[u8]) -> Vec<u8> {
+    let mut hash = vec![0u8; HASH_LEN];
+    pbkdf2::<Hmac<Sha256>>(password.as_bytes(), salt, HASH_ROUNDS, &mut hash);
+    hash
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn from_hex(text: &str) -> Option<Vec<u8>> {
+    if text.len() % 2 != 0 {
+        return None;
+    }
+
+    (0..text.len())
+        .step_by(2)
+        .map(|at| u8::from_str_radix(text.get(at..at + 2)?, 16).ok())
+        .collect()
+}
+
+pub enum LoginResult {
+    Success,
+    // The name hasn't been claimed, so anyone may play it.
+    NoAccount,
+    WrongPassword,
+    // Too many failed attempts; carries how long until the next try.
+    LockedOut(Duration),
+}
+
+#[derive(Default)]
+struct Failures {
+    count: u32,
+    locked_until: Option<Instant>,
+}
+
+// Passwords for claimed character names, stored salted and hashed. Failed
+// logins are only counted in memory, so a restart lifts any lockouts.
+pub struct AccountStore {
+    path: PathBuf,
+    accounts: Mutex<HashMap<String, Credentials>>,
+    failures: Mutex<HashMap<String, Failures>>,
+    max_failures: u32,
+    lockout: Duration,
+}
+
+impl AccountStore {
+    pub fn open<P: AsRef<Path>>(
+        path: P,
+        max_failures: u32,
+        lockout: Duration,
+    ) -> io::Result<AccountStore> {
+        let path = path.as_ref().to_path_buf();
+
+        let accounts = if path.exists() {
+            let mut contents = String::new();
+            File::open(&path)?.read_to_string(&mut contents)?;
+            serde_json::from_str(&contents)?
+        } else {
+            HashMap::new()
+        };
+
+        Ok(AccountStore {
+            path,
+            accounts: Mutex::new(accounts),
+            failures: Mutex::new(HashMap::new()),
+            max_failures,
+            lockout,
+        })
+    }
+
+    pub fn has_account(&self, name: &str) -> bool {
+        self.accounts.lock().unwrap().contains_key(name)
+    }
+
+    pub fn login(&self, name: &str, password: &str, now: Instant) -> LoginResult {
+        let credentials = match self.accounts.lock().unwrap().get(name) {
+            Some(credentials) => credentials.clone(),
+            None => return LoginResult::NoAccount,
+        };
+
+        let mut failures = self.failures.lock().unwrap();
+        let entry = failures.entry(name.to_string()).or_default();
+
+        if let Some(locked_until) = entry.locked_until {
+            if now < locked_until {
+                return LoginResult::LockedOut(locked_until - now);
+            }
+            *entry = Failures::default();
+        }
+
+        if credentials.matches(password) {
+            failures.remove(name);
+            return LoginResult::Success;
+        }
+
+        entry.count += 1;
+        if entry.count >= self.max_failures {
+            println!("Locking {} out after {} failed logins.", name, entry.count);
+            entry.locked_until = Some(now + self.lockout);
+        }
+        LoginResult::WrongPassword
+    }
+
+    // Claims the name, or changes the password of an existing account.
+    pub fn set_password(&self, name: &str, password: &str) -> io::Result<()> {
+        let mut accounts = self.accounts.lock().unwrap();
+        accounts.insert(name.to_string(), Credentials::new(password));
+        write_json(&self.path, &*accounts)
+    }
+
+    // Forgets the name's password and any lockout, so the next login can claim
+    // it again. Returns whether there was an account to reset.
+    pub fn reset(&self, name: &str) -> io::Result<bool> {
+        self.failures.lock().unwrap().remove(name);
+
+        let mut accounts = self.accounts.lock().unwrap();
+        if accounts.remove(name).is_none() {
+            return Ok(false);
+        }
+
+        write_json(&self.path, &*accounts)?;
+        Ok(true)
+    }
+}
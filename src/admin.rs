@@ -0,0 +1,88 @@
+// Messages addressed to this name are treated as commands rather than chat.
+pub const SERVER_NAME: &str = "Server";
+
+pub enum AdminCommand {
+    Kick(String),
+    Teleport(String, u16),
+    Spawn(u16, String),
+    // Heals the admin when no player is named.
+    Heal(Option<String>),
+    GiveGold(String, u16),
+    RespawnRoom(u16),
+    Mute(String),
+    Unmute(String),
+}
+
+const USAGE: &str = "Commands: /kick <player>, /teleport <player> <room>, \
+                     /spawn <room> <monster>, /heal [player], /give-gold <player> <amount>, \
+                     /respawn-room <room>, /mute <player>, /unmute <player>";
+
+// Player names may contain spaces, so a name is always whatever is left over
+// once the other arguments have been taken off.
+pub fn parse_command(text: &str) -> Result<AdminCommand, String> {
+    let text = text.trim();
+    let (command, args) = match text.find(' ') {
+        Some(at) => (&text[..at], text[at..].trim()),
+        None => (text, ""),
+    };
+
+    match command {
+        "/kick" => Ok(AdminCommand::Kick(name_arg(args, "/kick <player>")?)),
+        "/teleport" => {
+            let (name, room) = split_last(args, "/teleport <player> <room>")?;
+            Ok(AdminCommand::Teleport(name, room))
+        }
+        "/spawn" => {
+            let (room, monster) = split_first(args, "/spawn <room> <monster>")?;
+            Ok(AdminCommand::Spawn(room, monster))
+        }
+        "/heal" => Ok(AdminCommand::Heal(if args.is_empty() {
+            None
+        } else {
+            Some(args.to_string())
+        })),
+        "/give-gold" => {
+            let (name, amount) = split_last(args, "/give-gold <player> <amount>")?;
+            Ok(AdminCommand::GiveGold(name, amount))
+        }
+        "/respawn-room" => {
+            let room = args
+                .parse()
+                .map_err(|_| "Usage: /respawn-room <room>".to_string())?;
+            Ok(AdminCommand::RespawnRoom(room))
+        }
+        "/mute" => Ok(AdminCommand::Mute(name_arg(args, "/mute <player>")?)),
+        "/unmute" => Ok(AdminCommand::Unmute(name_arg(args, "/unmute <player>")?)),
+        _ => Err(USAGE.to_string()),
+    }
+}
+
+fn name_arg(args: &str, usage: &str) -> Result<String, String> {
+    if args.is_empty() {
+        Err(format!("Usage: {}", usage))
+    } else {
+        Ok(args.to_string())
+    }
+}
+
+fn split_last(args: &str, usage: &str) -> Result<(String, u16), String> {
+    let mut parts = args.rsplitn(2, ' ');
+    let number = parts.next().and_then(|number| number.parse().ok());
+    let name = parts.next().map(|name| name.trim()).unwrap_or("");
+
+    match number {
+        Some(number) if !name.is_empty() => Ok((name.to_string(), number)),
+        _ => Err(format!("Usage: {}", usage)),
+    }
+}
+
+fn split_first(args: &str, usage: &str) -> Result<(u16, String), String> {
+    let mut parts = args.splitn(2, ' ');
+    let number = parts.next().and_then(|number| number.parse().ok());
+    let name = parts.next().map(|name| name.trim()).unwrap_or("");
+
+    match number {
+        Some(number) if !name.is_empty() => Ok((number, name.to_string())),
+        _ => Err(format!("Usage: {}", usage)),
+    }
+}
@@ -0,0 +1,83 @@
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+// Reserved receivers that broadcast instead of naming a single player.
+pub const ROOM_CHANNEL: &str = "Room";
+pub const ALL_CHANNEL: &str = "All";
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Channel {
+    Room,
+    All,
+}
+
+impl Channel {
+    pub fn from_receiver(receiver: &str) -> Option<Channel> {
+        match receiver {
+            ROOM_CHANNEL => Some(Channel::Room),
+            ALL_CHANNEL => Some(Channel::All),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Channel::Room => ROOM_CHANNEL,
+            Channel::All => ALL_CHANNEL,
+        }
+    }
+}
+
+// Allows each client a fixed number of messages per channel within a sliding
+// window.
+pub struct ChatLimiter {
+    window: Duration,
+    room_limit: usize,
+    all_limit: usize,
+    recent: HashMap<(Uuid, Channel), VecDeque<Instant>>,
+}
+
+impl ChatLimiter {
+    pub fn new(window: Duration, room_limit: usize, all_limit: usize) -> ChatLimiter {
+        ChatLimiter {
+            window,
+            room_limit,
+            all_limit,
+            recent: HashMap::new(),
+        }
+    }
+
+    // Records the message and returns true if the client is still under the
+    // channel's limit.
+    pub fn try_send(&mut self, client_id: Uuid, channel: Channel, now: Instant) -> bool {
+        let limit = match channel {
+            Channel::Room => self.room_limit,
+            Channel::All => self.all_limit,
+        };
+
+        let window = self.window;
+        let sent = self
+            .recent
+            .entry((client_id, channel))
+            .or_insert_with(VecDeque::new);
+
+        while sent
+            .front()
+            .map_or(false, |&at| now.duration_since(at) >= window)
+        {
+            sent.pop_front();
+        }
+
+        if sent.len() >= limit {
+            return false;
+        }
+
+        sent.push_back(now);
+        true
+    }
+
+    pub fn forget(&mut self, client_id: &Uuid) {
+        self.recent.retain(|&(id, _), _| id != *client_id);
+    }
+}
@@ -1,5 +1,8 @@
-use entity::Entity;
-use rand::{thread_rng, Rng};
+use entity::{Aggression, Entity};
+use progression;
+use rand::Rng;
+use rng::GameRng;
+use std::collections::VecDeque;
 
 fn get_initiative_values(lentity: &Entity, rentity: &Entity) -> (f32, f32) {
     let lattack = lentity.get_effective_attack() as f32;
@@ -12,7 +15,7 @@ fn get_hit_chance(attack: u16, defense: u16) -> f32 {
     ((attack as f32 * 0.75f32) / defense as f32).max(0.9f32)
 }
 
-fn get_damage(attack: u16, defense: u16) -> f32 {
+fn get_damage(attack: u16, defense: u16, rng: &mut GameRng) -> f32 {
     let min_damage = (attack as f32 - (defense as f32 * 1.5)).max(attack as f32 * 0.1);
     let mut max_damage = attack as f32;
     println!("Min dmg: {}", min_damage);
@@ -22,130 +25,444 @@ fn get_damage(attack: u16, defense: u16) -> f32 {
         max_damage = min_damage + 1f32;
     }
 
-    thread_rng().gen_range(min_damage, max_damage)
+    rng.gen_range(min_damage, max_damage)
 }
 
-pub fn handle_fight(lentity: &mut Entity, rentity: &mut Entity) -> String {
-    let mut fight_result_message = String::new();
+#[derive(Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum CombatEvent {
+    Initiative {
+        name: String,
+    },
+    AttackAttempt {
+        attacker: String,
+        defender: String,
+        counter: bool,
+    },
+    Hit {
+        attacker: String,
+        defender: String,
+        damage: i16,
+    },
+    Miss {
+        attacker: String,
+        defender: String,
+    },
+    Death {
+        name: String,
+    },
+    Regen {
+        name: String,
+        amount: i16,
+    },
+    Experience {
+        name: String,
+        amount: u32,
+    },
+}
+
+impl CombatEvent {
+    pub fn to_text(&self) -> String {
+        match *self {
+            // Initiative is implied by who attacks first.
+            CombatEvent::Initiative { .. } => String::new(),
+            CombatEvent::AttackAttempt {
+                ref attacker,
+                ref defender,
+                counter,
+            } => {
+                if counter {
+                    format!("{} attempts to strike back!\n", attacker)
+                } else {
+                    format!("{} tries to hit {}.\n", attacker, defender)
+                }
+            }
+            CombatEvent::Hit { damage, .. } => format!("They hit for {} damage!\n", damage),
+            CombatEvent::Miss { .. } => "They miss!\n".to_string(),
+            CombatEvent::Death { ref name } => format!("{} has fallen!\n", name),
+            CombatEvent::Regen { ref name, amount } => {
+                format!("{} regenerated {} health.\n", name, amount)
+            }
+            CombatEvent::Experience { ref name, amount } => {
+                format!("{} gains {} experience.\n", name, amount)
+            }
+        }
+    }
+}
+
+pub fn render_events(events: &[CombatEvent]) -> String {
+    events.iter().map(|event| event.to_text()).collect()
+}
+
+fn attack(
+    attacker: &mut Entity,
+    defender: &mut Entity,
+    counter: bool,
+    rng: &mut GameRng,
+    events: &mut Vec<CombatEvent>,
+) {
+    events.push(CombatEvent::AttackAttempt {
+        attacker: attacker.name.clone(),
+        defender: defender.name.clone(),
+        counter,
+    });
+
+    // Hit or miss
+    if rng.gen::<f32>()
+        < get_hit_chance(
+            attacker.get_effective_attack(),
+            defender.get_effective_defense(),
+        )
+    {
+        // Damage rolls on the base stats plus whatever is equipped.
+        let attack = attacker
+            .attack
+            .saturating_add(attacker.inventory.attack_bonus());
+        let defense = defender
+            .defense
+            .saturating_add(defender.inventory.defense_bonus());
+        let dmg = get_damage(attack, defense, rng);
+        events.push(CombatEvent::Hit {
+            attacker: attacker.name.clone(),
+            defender: defender.name.clone(),
+            damage: dmg.floor() as i16,
+        });
+        defender.health = (defender.health - dmg as i16).max(0i16);
+        if defender.aggression == Aggression::WhenHit && !attacker.monster {
+            defender.hostile = true;
+        }
+        if defender.health == 0 {
+            defender.alive = false;
+            events.push(CombatEvent::Death {
+                name: defender.name.clone(),
+            });
+
+            // Only monster kills are worth experience.
+            if defender.monster && !attacker.monster {
+                let amount = progression::kill_experience(defender);
+                attacker.experience = attacker.experience.saturating_add(amount);
+                events.push(CombatEvent::Experience {
+                    name: attacker.name.clone(),
+                    amount,
+                });
+            }
+        }
+    } else {
+        events.push(CombatEvent::Miss {
+            attacker: attacker.name.clone(),
+            defender: defender.name.clone(),
+        });
+    }
+}
+
+fn regen(entity: &mut Entity, events: &mut Vec<CombatEvent>) {
+    if entity.alive {
+        let org_health = entity.health;
+        entity.regen();
+        let amount = entity.health - org_health;
+        if amount > 0 {
+            events.push(CombatEvent::Regen {
+                name: entity.name.clone(),
+                amount,
+            });
+        }
+    }
+}
+
+pub fn handle_fight(
+    lentity: &mut Entity,
+    rentity: &mut Entity,
+    rng: &mut GameRng,
+) -> Vec<CombatEvent> {
+    let mut events = vec![];
 
     let max_initiative = 2f32.sqrt();
 
     let (linitiative, _) = get_initiative_values(lentity, rentity);
 
-    let init_gen: f32 = thread_rng().gen::<f32>() * max_initiative;
+    let init_gen: f32 = rng.gen::<f32>() * max_initiative;
 
     // lentity has initiative
     if init_gen < linitiative {
-        fight_result_message
-            .push_str(format!("{} tries to hit {}.\n", lentity.name, rentity.name).as_str());
-        // Hit or miss
-        if thread_rng().gen::<f32>()
-            < get_hit_chance(
-                lentity.get_effective_attack(),
-                rentity.get_effective_defense(),
-            )
-        {
-            let dmg = get_damage(lentity.attack, rentity.defense);
-            fight_result_message
-                .push_str(format!("They hit for {} damage!\n", dmg.floor()).as_str());
-            rentity.health = (rentity.health - dmg as i16).max(0i16);
-            if rentity.health == 0 {
-                rentity.alive = false;
-                fight_result_message.push_str(format!("{} has fallen!\n", rentity.name).as_str());
-            }
-        } else {
-            fight_result_message.push_str(format!("They miss!\n").as_str());
-        }
-
+        events.push(CombatEvent::Initiative {
+            name: lentity.name.clone(),
+        });
+        attack(lentity, rentity, false, rng, &mut events);
         if rentity.alive {
-            fight_result_message
-                .push_str(format!("{} attempts to strike back!\n", rentity.name).as_str());
-            if thread_rng().gen::<f32>()
-                < get_hit_chance(
-                    rentity.get_effective_attack(),
-                    lentity.get_effective_defense(),
-                )
-            {
-                let dmg = get_damage(rentity.attack, lentity.defense);
-                fight_result_message
-                    .push_str(format!("They hit for {} damage!\n", dmg.floor()).as_str());
-                lentity.health = (lentity.health - dmg as i16).max(0i16);
-                if lentity.health == 0 {
-                    lentity.alive = false;
-                    fight_result_message
-                        .push_str(format!("{} has fallen!\n", lentity.name).as_str());
-                }
-            } else {
-                fight_result_message.push_str(format!("They miss!\n").as_str());
-            }
+            attack(rentity, lentity, true, rng, &mut events);
         }
     } else {
-        fight_result_message
-            .push_str(format!("{} tries to hit {}.\n", rentity.name, lentity.name).as_str());
-        if thread_rng().gen::<f32>()
-            < get_hit_chance(
-                rentity.get_effective_attack(),
-                lentity.get_effective_defense(),
-            )
-        {
-            let dmg = get_damage(rentity.attack, lentity.defense);
-            fight_result_message
-                .push_str(format!("They hit for {} damage!\n", dmg.floor()).as_str());
-            lentity.health = (lentity.health - dmg as i16).max(0i16);
-            if lentity.health == 0 {
-                lentity.alive = false;
-                fight_result_message.push_str(format!("{} has fallen!\n", lentity.name).as_str());
-            }
+        events.push(CombatEvent::Initiative {
+            name: rentity.name.clone(),
+        });
+        attack(rentity, lentity, false, rng, &mut events);
+        if lentity.alive {
+            attack(lentity, rentity, true, rng, &mut events);
+        }
+    }
+
+    regen(lentity, &mut events);
+    regen(rentity, &mut events);
+
+    lentity.update_dirty = true;
+    rentity.update_dirty = true;
+
+    events
+}
+
+// One round of a fight between a group of players and a group of monsters.
+// Everyone acts once in initiative order, attacking a random living member of
+// the other side.
+pub fn handle_group_fight<'a>(
+    players: &mut Vec<&'a mut Entity>,
+    monsters: &mut Vec<&'a mut Entity>,
+    rng: &mut GameRng,
+) -> Vec<CombatEvent> {
+    let mut events = vec![];
+
+    // (is_player, index, initiative)
+    let mut turn_order: Vec<(bool, usize, f32)> = vec![];
+    for (idx, player) in players.iter().enumerate() {
+        turn_order.push((
+            true,
+            idx,
+            rng.gen::<f32>() * player.get_effective_attack() as f32,
+        ));
+    }
+    for (idx, monster) in monsters.iter().enumerate() {
+        turn_order.push((
+            false,
+            idx,
+            rng.gen::<f32>() * monster.get_effective_attack() as f32,
+        ));
+    }
+    turn_order.sort_by(|l, r| r.2.partial_cmp(&l.2).unwrap());
+
+    for &(is_player, idx, _) in turn_order.iter() {
+        let (attackers, defenders) = if is_player {
+            (&mut *players, &mut *monsters)
         } else {
-            fight_result_message.push_str(format!("They miss!\n").as_str());
+            (&mut *monsters, &mut *players)
+        };
+
+        if !attackers[idx].alive {
+            continue;
         }
 
-        if lentity.alive {
-            fight_result_message
-                .push_str(format!("{} attempts to strike back!\n", lentity.name).as_str());
-            if thread_rng().gen::<f32>()
-                < get_hit_chance(
-                    lentity.get_effective_attack(),
-                    rentity.get_effective_defense(),
-                )
-            {
-                let dmg = get_damage(lentity.attack, rentity.defense);
-                fight_result_message
-                    .push_str(format!("They hit for {} damage!\n", dmg.floor()).as_str());
-                rentity.health = (rentity.health - dmg as i16).max(0i16);
-                if rentity.health == 0 {
-                    rentity.alive = false;
-                    fight_result_message
-                        .push_str(format!("{} has fallen!\n", rentity.name).as_str());
-                }
-            } else {
-                fight_result_message.push_str(format!("They miss!\n").as_str());
-            }
+        let targets: Vec<usize> = (0..defenders.len())
+            .filter(|&i| defenders[i].alive)
+            .collect();
+        if targets.is_empty() {
+            break;
         }
+
+        if events.is_empty() {
+            events.push(CombatEvent::Initiative {
+                name: attackers[idx].name.clone(),
+            });
+        }
+
+        let target = targets[rng.gen_range(0, targets.len())];
+        attack(
+            &mut *attackers[idx],
+            &mut *defenders[target],
+            false,
+            rng,
+            &mut events,
+        );
     }
 
-    if lentity.alive {
-        let org_health = lentity.health;
-        lentity.regen();
-        let amount = lentity.health - org_health;
-        if amount > 0 {
-            fight_result_message
-                .push_str(format!("{} regenerated {} health.\n", lentity.name, amount).as_str());
+    for entity in players.iter_mut().chain(monsters.iter_mut()) {
+        regen(entity, &mut events);
+        entity.update_dirty = true;
+    }
+
+    events
+}
+
+// The most recent fights, kept for the REST service.
+#[derive(Serialize)]
+pub struct CombatRecord {
+    pub room: u16,
+    pub events: Vec<CombatEvent>,
+}
+
+pub struct CombatLog {
+    records: VecDeque<CombatRecord>,
+    capacity: usize,
+}
+
+impl CombatLog {
+    pub fn new(capacity: usize) -> CombatLog {
+        CombatLog {
+            records: VecDeque::new(),
+            capacity,
         }
     }
 
-    if rentity.alive {
-        let org_health = rentity.health;
-        rentity.regen();
-        let amount = rentity.health - org_health;
-        if amount > 0 {
-            fight_result_message
-                .push_str(format!("{} regenerated {} health.\n", rentity.name, amount).as_str());
+    pub fn record(&mut self, room: u16, events: Vec<CombatEvent>) {
+        if self.records.len() == self.capacity {
+            self.records.pop_front();
         }
+        self.records.push_back(CombatRecord { room, events });
     }
 
-    lentity.update_dirty = true;
-    rentity.update_dirty = true;
+    pub fn records(&self) -> &VecDeque<CombatRecord> {
+        &self.records
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use entity::test_entity;
+    use rng::seeded_rng;
+
+    // Always hits and kills anything with ordinary health in one blow.
+    fn champion() -> Entity {
+        let mut player = test_entity("Champion", false);
+        player.attack = 1000;
+        player
+    }
+
+    // Can't do any harm.
+    fn weakling(name: &str) -> Entity {
+        let mut monster = test_entity(name, true);
+        monster.attack = 0;
+        monster.defense = 1;
+        monster
+    }
+
+    fn has_attack(events: &[CombatEvent], by: &str, on: &str) -> bool {
+        events.iter().any(|event| match *event {
+            CombatEvent::AttackAttempt {
+                ref attacker,
+                ref defender,
+                ..
+            } => attacker == by && defender == on,
+            _ => false,
+        })
+    }
+
+    fn has_death(events: &[CombatEvent], of: &str) -> bool {
+        events.iter().any(|event| match *event {
+            CombatEvent::Death { ref name } => name == of,
+            _ => false,
+        })
+    }
 
-    fight_result_message
+    fn experience_for(events: &[CombatEvent], of: &str) -> Option<u32> {
+        events
+            .iter()
+            .filter_map(|event| match *event {
+                CombatEvent::Experience { ref name, amount } if name == of => Some(amount),
+                _ => None,
+            })
+            .next()
+    }
+
+    #[test]
+    fn killing_a_monster_is_reported_and_earns_experience() {
+        let mut rng = seeded_rng(1);
+        let mut player = champion();
+        let mut monster = weakling("Rat");
+
+        let events = handle_fight(&mut player, &mut monster, &mut rng);
+
+        assert!(has_attack(&events, "Champion", "Rat"));
+        assert!(events.iter().any(|event| match *event {
+            CombatEvent::Hit {
+                ref defender,
+                damage,
+                ..
+            } => defender == "Rat" && damage >= 100,
+            _ => false,
+        }));
+        assert!(has_death(&events, "Rat"));
+        assert!(!monster.alive);
+
+        let amount = progression::kill_experience(&monster);
+        assert_eq!(experience_for(&events, "Champion"), Some(amount));
+        assert_eq!(player.experience, amount);
+    }
+
+    #[test]
+    fn players_killed_by_monsters_earn_nothing() {
+        let mut rng = seeded_rng(1);
+        let mut player = test_entity("Victim", false);
+        player.attack = 0;
+        player.defense = 1;
+        let mut monster = champion();
+        monster.monster = true;
+
+        let events = handle_fight(&mut player, &mut monster, &mut rng);
+
+        assert!(has_death(&events, "Victim"));
+        assert_eq!(experience_for(&events, "Champion"), None);
+        assert_eq!(monster.experience, 0);
+    }
+
+    #[test]
+    fn monsters_that_fight_back_when_hit_turn_hostile() {
+        let mut rng = seeded_rng(1);
+        let mut player = champion();
+        let mut monster = weakling("Badger");
+        monster.health = 30000;
+        monster.aggression = Aggression::WhenHit;
+
+        assert!(!monster.hostile);
+        let events = handle_fight(&mut player, &mut monster, &mut rng);
+
+        assert!(has_attack(&events, "Champion", "Badger"));
+        assert!(monster.alive);
+        assert!(monster.hostile);
+    }
+
+    #[test]
+    fn monsters_that_never_fight_stay_calm_when_hit() {
+        let mut rng = seeded_rng(1);
+        let mut player = champion();
+        let mut monster = weakling("Sloth");
+        monster.health = 30000;
+
+        handle_fight(&mut player, &mut monster, &mut rng);
+
+        assert!(!monster.hostile);
+    }
+
+    #[test]
+    fn group_fights_give_everyone_a_turn() {
+        let mut rng = seeded_rng(1);
+        let mut alice = champion();
+        alice.name = "Alice".to_string();
+        let mut bob = champion();
+        bob.name = "Bob".to_string();
+        let mut rat = weakling("Rat");
+        let mut spider = weakling("Spider");
+
+        let events = {
+            let mut players = vec![&mut alice, &mut bob];
+            let mut monsters = vec![&mut rat, &mut spider];
+            handle_group_fight(&mut players, &mut monsters, &mut rng)
+        };
+
+        // Each player kills one monster, so both die and both earn experience.
+        assert!(has_death(&events, "Rat"));
+        assert!(has_death(&events, "Spider"));
+        assert!(experience_for(&events, "Alice").is_some());
+        assert!(experience_for(&events, "Bob").is_some());
+        assert!(alice.alive && bob.alive);
+    }
+
+    #[test]
+    fn the_same_seed_gives_the_same_fight() {
+        let fight = |seed| {
+            let mut rng = seeded_rng(seed);
+            let mut player = test_entity("Hero", false);
+            let mut monster = test_entity("Ogre", true);
+            render_events(&handle_fight(&mut player, &mut monster, &mut rng))
+        };
+
+        assert_eq!(fight(7), fight(7));
+    }
 }
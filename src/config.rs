@@ -0,0 +1,551 @@
+use accounts::DEFAULT_ACCOUNTS_PATH;
+use items::DEFAULT_ITEMS_PATH;
+use map_file::DEFAULT_MAP_PATH;
+use monster_catalogue::DEFAULT_CATALOGUE_PATH;
+use persistence::DEFAULT_STORE_PATH;
+use rate_limit::{Limit, PacketKind};
+use shop::DEFAULT_SHOPS_PATH;
+use std::fmt;
+use std::fs::File;
+use std::io::Read;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::str::FromStr;
+use std::time::Duration;
+use toml;
+
+pub const DEFAULT_CONFIG_PATH: &str = "server.toml";
+
+#[derive(Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DeathPolicy {
+    // Dead players come back at the start room after the respawn delay.
+    Respawn,
+    // Dead characters are deleted once the respawn delay is up.
+    Permadeath,
+}
+
+impl FromStr for DeathPolicy {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<DeathPolicy, ()> {
+        match s {
+            "respawn" => Ok(DeathPolicy::Respawn),
+            "permadeath" => Ok(DeathPolicy::Permadeath),
+            _ => Err(()),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(String, String),
+    Parse(String, String),
+    MissingValue(String),
+    BadValue(String, String),
+    UnexpectedArgument(String),
+    Invalid(String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ConfigError::Io(ref path, ref reason) => {
+                write!(f, "Failed to read config file '{}': {}", path, reason)
+            }
+            ConfigError::Parse(ref path, ref reason) => {
+                write!(f, "Invalid config file '{}': {}", path, reason)
+            }
+            ConfigError::MissingValue(ref flag) => write!(f, "Option {} needs a value.", flag),
+            ConfigError::BadValue(ref flag, ref value) => {
+                write!(f, "Invalid value '{}' for option {}.", value, flag)
+            }
+            ConfigError::UnexpectedArgument(ref arg) => {
+                write!(f, "Unexpected argument '{}'.", arg)
+            }
+            ConfigError::Invalid(ref reason) => write!(f, "Invalid configuration: {}", reason),
+        }
+    }
+}
+
+// Everything that can be set in the config file. Each value can also be
+// overridden on the command line, e.g. `stat_limit` by `--stat-limit`.
+#[derive(Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    pub address: Option<SocketAddr>,
+    // The REST service only runs when this is set.
+    pub rest_address: Option<SocketAddr>,
+    // Bearer token for the REST admin endpoints, which are off when unset.
+    pub rest_admin_token: Option<String>,
+    pub map: String,
+    pub monsters: String,
+    pub items: String,
+    pub shops: String,
+    pub store: String,
+    pub accounts: String,
+    // A random seed is picked when this is not set.
+    pub seed: Option<u64>,
+    pub initial_points: u16,
+    pub stat_limit: u16,
+    pub default_health: i16,
+    pub default_gold: u16,
+    // Seconds a client may stay silent before it is dropped.
+    pub client_timeout: u64,
+    // Milliseconds between game updates.
+    pub tick_interval: u64,
+    pub description: String,
+    // Character names allowed to send commands to the server. Each must
+    // already be claimed with a password, or nobody can play it.
+    pub admins: Vec<String>,
+    // Seconds over which the chat limits below are counted.
+    pub chat_window: u64,
+    // Messages a player may send to each channel per window. Zero disables
+    // the channel.
+    pub room_chat_limit: usize,
+    pub all_chat_limit: usize,
+    // Character names that start out muted.
+    pub muted: Vec<String>,
+    // Player versus player fights. Rooms marked as a sanctuary are always safe.
+    pub pvp: bool,
+    pub death_policy: DeathPolicy,
+    // Seconds a dead player lies in the room, lootable, before respawning or
+    // being deleted.
+    pub respawn_delay: u64,
+    // Percentage of their remaining gold a player loses on respawning.
+    pub death_gold_penalty: u8,
+    // Stat points granted for each level gained.
+    pub points_per_level: u16,
+    // Seconds between attacks from each hostile monster.
+    pub monster_attack_interval: u64,
+    // Whether a new character must claim its name with a password.
+    pub require_password: bool,
+    // Failed logins in a row before a name is locked for login_lockout seconds.
+    pub max_login_failures: u32,
+    pub login_lockout: u64,
+    // Seconds a disconnected player's character stays in the world, linkdead,
+    // for them to reconnect to. Zero removes it straight away.
+    pub reconnect_grace: u64,
+    // Seconds without a packet before a started player is shown as AFK, and
+    // before they are saved and removed from the game. Zero disables either.
+    pub afk_timeout: u64,
+    pub idle_timeout: u64,
+    // Token buckets for each kind of packet a client could flood: how many may
+    // be sent at once, and how many more each second after that. A burst of
+    // zero turns the limit off.
+    pub fight_burst: u32,
+    pub fight_rate: f64,
+    pub loot_burst: u32,
+    pub loot_rate: f64,
+    pub message_burst: u32,
+    pub message_rate: f64,
+    pub change_room_burst: u32,
+    pub change_room_rate: f64,
+    pub character_burst: u32,
+    pub character_rate: f64,
+    pub start_burst: u32,
+    pub start_rate: f64,
+    // Limited packets in a row before a client is dropped. Zero never drops.
+    pub rate_limit_strikes: u32,
+}
+
+impl Default for ServerConfig {
+    fn default() -> ServerConfig {
+        ServerConfig {
+            address: None,
+            rest_address: None,
+            rest_admin_token: None,
+            map: DEFAULT_MAP_PATH.to_string(),
+            monsters: DEFAULT_CATALOGUE_PATH.to_string(),
+            items: DEFAULT_ITEMS_PATH.to_string(),
+            shops: DEFAULT_SHOPS_PATH.to_string(),
+            store: DEFAULT_STORE_PATH.to_string(),
+            accounts: DEFAULT_ACCOUNTS_PATH.to_string(),
+            seed: None,
+            initial_points: 600,
+            stat_limit: u16::max_value(),
+            default_health: 500,
+            default_gold: 0,
+            client_timeout: 20,
+            tick_interval: 1000,
+            description: "You find yourself in an uneventful boring dungeon.".to_string(),
+            admins: vec![],
+            chat_window: 10,
+            room_chat_limit: 5,
+            all_chat_limit: 2,
+            muted: vec![],
+            pvp: false,
+            death_policy: DeathPolicy::Respawn,
+            respawn_delay: 10,
+            death_gold_penalty: 25,
+            points_per_level: 30,
+            monster_attack_interval: 3,
+            require_password: false,
+            max_login_failures: 5,
+            login_lockout: 300,
+            reconnect_grace: 60,
+            afk_timeout: 300,
+            idle_timeout: 1800,
+            fight_burst: 3,
+            fight_rate: 1.0,
+            loot_burst: 5,
+            loot_rate: 2.0,
+            message_burst: 10,
+            message_rate: 2.0,
+            change_room_burst: 5,
+            change_room_rate: 2.0,
+            character_burst: 5,
+            character_rate: 1.0,
+            start_burst: 3,
+            start_rate: 1.0,
+            rate_limit_strikes: 20,
+        }
+    }
+}
+
+impl ServerConfig {
+    pub fn client_timeout(&self) -> Duration {
+        Duration::from_secs(self.client_timeout)
+    }
+
+    pub fn tick_interval(&self) -> Duration {
+        Duration::from_millis(self.tick_interval)
+    }
+
+    pub fn chat_window(&self) -> Duration {
+        Duration::from_secs(self.chat_window)
+    }
+
+    pub fn respawn_delay(&self) -> Duration {
+        Duration::from_secs(self.respawn_delay)
+    }
+
+    pub fn monster_attack_interval(&self) -> Duration {
+        Duration::from_secs(self.monster_attack_interval)
+    }
+
+    pub fn login_lockout(&self) -> Duration {
+        Duration::from_secs(self.login_lockout)
+    }
+
+    pub fn reconnect_grace(&self) -> Duration {
+        Duration::from_secs(self.reconnect_grace)
+    }
+
+    pub fn afk_timeout(&self) -> Duration {
+        Duration::from_secs(self.afk_timeout)
+    }
+
+    pub fn idle_timeout(&self) -> Duration {
+        Duration::from_secs(self.idle_timeout)
+    }
+
+    pub fn packet_limit(&self, kind: PacketKind) -> Limit {
+        let (burst, per_second) = match kind {
+            PacketKind::Fight => (self.fight_burst, self.fight_rate),
+            PacketKind::Loot => (self.loot_burst, self.loot_rate),
+            PacketKind::Message => (self.message_burst, self.message_rate),
+            PacketKind::ChangeRoom => (self.change_room_burst, self.change_room_rate),
+            PacketKind::Character => (self.character_burst, self.character_rate),
+            PacketKind::Start => (self.start_burst, self.start_rate),
+        };
+        Limit { burst, per_second }
+    }
+
+    fn validate(&self) -> Result<(), ConfigError> {
+        if self.address.is_none() {
+            return Err(ConfigError::Invalid(
+                "no server address given; set `address` or pass --address.".to_string(),
+            ));
+        }
+
+        if self.initial_points == 0 {
+            return Err(ConfigError::Invalid(
+                "initial_points must be above zero.".to_string(),
+            ));
+        }
+
+        if self.stat_limit == 0 {
+            return Err(ConfigError::Invalid(
+                "stat_limit must be above zero.".to_string(),
+            ));
+        }
+
+        if self.default_health <= 0 {
+            return Err(ConfigError::Invalid(
+                "default_health must be above zero.".to_string(),
+            ));
+        }
+
+        if self.client_timeout == 0 {
+            return Err(ConfigError::Invalid(
+                "client_timeout must be above zero.".to_string(),
+            ));
+        }
+
+        if self.tick_interval == 0 {
+            return Err(ConfigError::Invalid(
+                "tick_interval must be above zero.".to_string(),
+            ));
+        }
+
+        if self.chat_window == 0 {
+            return Err(ConfigError::Invalid(
+                "chat_window must be above zero.".to_string(),
+            ));
+        }
+
+        if self.monster_attack_interval == 0 {
+            return Err(ConfigError::Invalid(
+                "monster_attack_interval must be above zero.".to_string(),
+            ));
+        }
+
+        if self.max_login_failures == 0 {
+            return Err(ConfigError::Invalid(
+                "max_login_failures must be above zero.".to_string(),
+            ));
+        }
+
+        if self.afk_timeout > 0 && self.idle_timeout > 0 && self.idle_timeout <= self.afk_timeout {
+            return Err(ConfigError::Invalid(
+                "idle_timeout must be longer than afk_timeout.".to_string(),
+            ));
+        }
+
+        let rates = [
+            ("fight_rate", self.fight_burst, self.fight_rate),
+            ("loot_rate", self.loot_burst, self.loot_rate),
+            ("message_rate", self.message_burst, self.message_rate),
+            (
+                "change_room_rate",
+                self.change_room_burst,
+                self.change_room_rate,
+            ),
+            ("character_rate", self.character_burst, self.character_rate),
+            ("start_rate", self.start_burst, self.start_rate),
+        ];
+        for &(name, burst, rate) in rates.iter() {
+            if burst > 0 && !(rate > 0.0 && rate.is_finite()) {
+                return Err(ConfigError::Invalid(format!(
+                    "{} must be above zero while its burst is set.",
+                    name
+                )));
+            }
+        }
+
+        if self.death_gold_penalty > 100 {
+            return Err(ConfigError::Invalid(
+                "death_gold_penalty is a percentage and can't be above 100.".to_string(),
+            ));
+        }
+
+        if self.description.len() >= u16::max_value() as usize {
+            return Err(ConfigError::Invalid(
+                "description is too long for a game packet.".to_string(),
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+// Reads the config file named by --config (or the default one, if present) and
+// applies the remaining command line options on top of it.
+pub fn load_config(args: Vec<String>) -> Result<ServerConfig, ConfigError> {
+    let mut args: Vec<String> = args.into_iter().skip(1).collect();
+
+    let mut config = match take_flag_value(&mut args, "--config")? {
+        Some(path) => read_config_file(&path)?,
+        None if Path::new(DEFAULT_CONFIG_PATH).exists() => read_config_file(DEFAULT_CONFIG_PATH)?,
+        None => ServerConfig::default(),
+    };
+
+    if let Some(address) = parse_flag_value(&mut args, "--address")? {
+        config.address = Some(address);
+    }
+    if let Some(rest_address) = parse_flag_value(&mut args, "--rest")? {
+        config.rest_address = Some(rest_address);
+    }
+    if let Some(token) = take_flag_value(&mut args, "--rest-admin-token")? {
+        config.rest_admin_token = Some(token);
+    }
+    if let Some(map) = take_flag_value(&mut args, "--map")? {
+        config.map = map;
+    }
+    if let Some(monsters) = take_flag_value(&mut args, "--monsters")? {
+        config.monsters = monsters;
+    }
+    if let Some(items) = take_flag_value(&mut args, "--items")? {
+        config.items = items;
+    }
+    if let Some(shops) = take_flag_value(&mut args, "--shops")? {
+        config.shops = shops;
+    }
+    if let Some(store) = take_flag_value(&mut args, "--store")? {
+        config.store = store;
+    }
+    if let Some(accounts) = take_flag_value(&mut args, "--accounts")? {
+        config.accounts = accounts;
+    }
+    if let Some(seed) = parse_flag_value(&mut args, "--seed")? {
+        config.seed = Some(seed);
+    }
+    if let Some(initial_points) = parse_flag_value(&mut args, "--initial-points")? {
+        config.initial_points = initial_points;
+    }
+    if let Some(stat_limit) = parse_flag_value(&mut args, "--stat-limit")? {
+        config.stat_limit = stat_limit;
+    }
+    if let Some(default_health) = parse_flag_value(&mut args, "--default-health")? {
+        config.default_health = default_health;
+    }
+    if let Some(default_gold) = parse_flag_value(&mut args, "--default-gold")? {
+        config.default_gold = default_gold;
+    }
+    if let Some(client_timeout) = parse_flag_value(&mut args, "--client-timeout")? {
+        config.client_timeout = client_timeout;
+    }
+    if let Some(tick_interval) = parse_flag_value(&mut args, "--tick-interval")? {
+        config.tick_interval = tick_interval;
+    }
+    if let Some(description) = take_flag_value(&mut args, "--description")? {
+        config.description = description;
+    }
+
+    while let Some(admin) = take_flag_value(&mut args, "--admin")? {
+        config.admins.push(admin);
+    }
+    if let Some(chat_window) = parse_flag_value(&mut args, "--chat-window")? {
+        config.chat_window = chat_window;
+    }
+    if let Some(room_chat_limit) = parse_flag_value(&mut args, "--room-chat-limit")? {
+        config.room_chat_limit = room_chat_limit;
+    }
+    if let Some(all_chat_limit) = parse_flag_value(&mut args, "--all-chat-limit")? {
+        config.all_chat_limit = all_chat_limit;
+    }
+    while let Some(muted) = take_flag_value(&mut args, "--mute")? {
+        config.muted.push(muted);
+    }
+    if let Some(pvp) = parse_flag_value(&mut args, "--pvp")? {
+        config.pvp = pvp;
+    }
+    if let Some(death_policy) = parse_flag_value(&mut args, "--death-policy")? {
+        config.death_policy = death_policy;
+    }
+    if let Some(respawn_delay) = parse_flag_value(&mut args, "--respawn-delay")? {
+        config.respawn_delay = respawn_delay;
+    }
+    if let Some(death_gold_penalty) = parse_flag_value(&mut args, "--death-gold-penalty")? {
+        config.death_gold_penalty = death_gold_penalty;
+    }
+
+    if let Some(points_per_level) = parse_flag_value(&mut args, "--points-per-level")? {
+        config.points_per_level = points_per_level;
+    }
+    if let Some(interval) = parse_flag_value(&mut args, "--monster-attack-interval")? {
+        config.monster_attack_interval = interval;
+    }
+    if let Some(require_password) = parse_flag_value(&mut args, "--require-password")? {
+        config.require_password = require_password;
+    }
+    if let Some(max_login_failures) = parse_flag_value(&mut args, "--max-login-failures")? {
+        config.max_login_failures = max_login_failures;
+    }
+    if let Some(login_lockout) = parse_flag_value(&mut args, "--login-lockout")? {
+        config.login_lockout = login_lockout;
+    }
+    if let Some(reconnect_grace) = parse_flag_value(&mut args, "--reconnect-grace")? {
+        config.reconnect_grace = reconnect_grace;
+    }
+    if let Some(afk_timeout) = parse_flag_value(&mut args, "--afk-timeout")? {
+        config.afk_timeout = afk_timeout;
+    }
+    if let Some(idle_timeout) = parse_flag_value(&mut args, "--idle-timeout")? {
+        config.idle_timeout = idle_timeout;
+    }
+    if let Some(fight_burst) = parse_flag_value(&mut args, "--fight-burst")? {
+        config.fight_burst = fight_burst;
+    }
+    if let Some(fight_rate) = parse_flag_value(&mut args, "--fight-rate")? {
+        config.fight_rate = fight_rate;
+    }
+    if let Some(loot_burst) = parse_flag_value(&mut args, "--loot-burst")? {
+        config.loot_burst = loot_burst;
+    }
+    if let Some(loot_rate) = parse_flag_value(&mut args, "--loot-rate")? {
+        config.loot_rate = loot_rate;
+    }
+    if let Some(message_burst) = parse_flag_value(&mut args, "--message-burst")? {
+        config.message_burst = message_burst;
+    }
+    if let Some(message_rate) = parse_flag_value(&mut args, "--message-rate")? {
+        config.message_rate = message_rate;
+    }
+    if let Some(burst) = parse_flag_value(&mut args, "--change-room-burst")? {
+        config.change_room_burst = burst;
+    }
+    if let Some(rate) = parse_flag_value(&mut args, "--change-room-rate")? {
+        config.change_room_rate = rate;
+    }
+    if let Some(burst) = parse_flag_value(&mut args, "--character-burst")? {
+        config.character_burst = burst;
+    }
+    if let Some(rate) = parse_flag_value(&mut args, "--character-rate")? {
+        config.character_rate = rate;
+    }
+    if let Some(burst) = parse_flag_value(&mut args, "--start-burst")? {
+        config.start_burst = burst;
+    }
+    if let Some(rate) = parse_flag_value(&mut args, "--start-rate")? {
+        config.start_rate = rate;
+    }
+    if let Some(strikes) = parse_flag_value(&mut args, "--rate-limit-strikes")? {
+        config.rate_limit_strikes = strikes;
+    }
+
+    if let Some(arg) = args.into_iter().next() {
+        return Err(ConfigError::UnexpectedArgument(arg));
+    }
+
+    config.validate()?;
+    Ok(config)
+}
+
+fn read_config_file(path: &str) -> Result<ServerConfig, ConfigError> {
+    let mut contents = String::new();
+    File::open(path)
+        .and_then(|mut file| file.read_to_string(&mut contents))
+        .map_err(|e| ConfigError::Io(path.to_string(), e.to_string()))?;
+
+    toml::from_str(&contents).map_err(|e| ConfigError::Parse(path.to_string(), e.to_string()))
+}
+
+// Removes "<flag> <value>" from the arguments, returning the value.
+fn take_flag_value(args: &mut Vec<String>, flag: &str) -> Result<Option<String>, ConfigError> {
+    let index = match args.iter().position(|arg| arg == flag) {
+        Some(index) => index,
+        None => return Ok(None),
+    };
+
+    args.remove(index);
+    if index < args.len() {
+        Ok(Some(args.remove(index)))
+    } else {
+        Err(ConfigError::MissingValue(flag.to_string()))
+    }
+}
+
+fn parse_flag_value<T: FromStr>(
+    args: &mut Vec<String>,
+    flag: &str,
+) -> Result<Option<T>, ConfigError> {
+    match take_flag_value(args, flag)? {
+        Some(value) => value
+            .parse()
+            .map(Some)
+            .map_err(|_| ConfigError::BadValue(flag.to_string(), value)),
+        None => Ok(None),
+    }
+}
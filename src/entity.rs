@@ -1,3 +1,22 @@
+use items::Inventory;
+
+// When a monster starts fights on its own.
+#[derive(Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Aggression {
+    // Only fights when a player starts it.
+    Never,
+    // Turns hostile once a player has hit it.
+    WhenHit,
+    Always,
+}
+
+impl Default for Aggression {
+    fn default() -> Aggression {
+        Aggression::Never
+    }
+}
+
 pub struct Entity {
     pub name: String,
     pub attack: u16,
@@ -11,6 +30,20 @@ pub struct Entity {
     pub desc: String,
     pub base_health: i16,
     pub update_dirty: bool,
+    pub experience: u32,
+    pub level: u16,
+    // Points earned by leveling that are yet to be spent on stats.
+    pub stat_points: u16,
+    pub inventory: Inventory,
+    // Chance each update that a monster wanders to an adjacent room. Always
+    // zero for players.
+    pub roam_chance: f32,
+    pub aggression: Aggression,
+    // Hostile monsters attack players in their room during updates.
+    pub hostile: bool,
+    // The room whose spawner made the monster. It counts towards that room's
+    // population wherever it wanders.
+    pub home: Option<u16>,
 }
 
 impl Entity {
@@ -24,15 +57,17 @@ impl Entity {
     }
 
     pub fn get_effective_attack(&self) -> u16 {
-        (self.attack as f32 * self.get_gold_skill_multiplier()) as u16
+        let attack = self.attack.saturating_add(self.inventory.attack_bonus());
+        (attack as f32 * self.get_gold_skill_multiplier()) as u16
     }
 
     pub fn get_effective_defense(&self) -> u16 {
-        (self.defense as f32 * self.get_gold_skill_multiplier()) as u16
+        let defense = self.defense.saturating_add(self.inventory.defense_bonus());
+        (defense as f32 * self.get_gold_skill_multiplier()) as u16
     }
 
     pub fn get_effective_regen(&self) -> u16 {
-        (self.defense as f32 * self.get_gold_skill_multiplier()) as u16
+        (self.regen as f32 * self.get_gold_skill_multiplier()) as u16
     }
 
     pub fn get_max_health(&self) -> i16 {
@@ -53,3 +88,30 @@ impl Entity {
         }
     }
 }
+
+// A bare entity for unit tests to adjust as they need.
+#[cfg(test)]
+pub fn test_entity(name: &str, monster: bool) -> Entity {
+    Entity {
+        name: name.to_string(),
+        attack: 100,
+        defense: 100,
+        regen: 100,
+        health: 100,
+        gold: 0,
+        location: 1,
+        alive: true,
+        monster,
+        desc: String::new(),
+        base_health: 100,
+        update_dirty: false,
+        experience: 0,
+        level: 1,
+        stat_points: 0,
+        inventory: Inventory::default(),
+        roam_chance: 0.0,
+        aggression: Aggression::Never,
+        hostile: false,
+        home: None,
+    }
+}
@@ -0,0 +1,228 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::File;
+use std::io::Read;
+use toml;
+
+pub const DEFAULT_ITEMS_PATH: &str = "data/items.toml";
+
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ItemKind {
+    Weapon,
+    Armour,
+    Potion,
+}
+
+impl ItemKind {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            ItemKind::Weapon => "weapon",
+            ItemKind::Armour => "armour",
+            ItemKind::Potion => "potion",
+        }
+    }
+}
+
+// Items are copied into inventories whole, so a character keeps what they
+// found even if the item file changes later.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Item {
+    pub name: String,
+    pub kind: ItemKind,
+    // Added to the wearer's stats while equipped.
+    #[serde(default)]
+    pub attack: u16,
+    #[serde(default)]
+    pub defense: u16,
+    // Health restored when a potion is used.
+    #[serde(default)]
+    pub heal: i16,
+    pub description: String,
+}
+
+impl Item {
+    pub fn summary(&self) -> String {
+        match self.kind {
+            ItemKind::Weapon => format!("{} (+{} attack)", self.name, self.attack),
+            ItemKind::Armour => format!("{} (+{} defense)", self.name, self.defense),
+            ItemKind::Potion => format!("{} (heals {})", self.name, self.heal),
+        }
+    }
+}
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct Inventory {
+    pub items: Vec<Item>,
+    pub weapon: Option<Item>,
+    pub armour: Option<Item>,
+}
+
+impl Inventory {
+    pub fn attack_bonus(&self) -> u16 {
+        self.weapon.as_ref().map_or(0, |item| item.attack)
+    }
+
+    pub fn defense_bonus(&self) -> u16 {
+        self.armour.as_ref().map_or(0, |item| item.defense)
+    }
+
+    // Takes the first carried item whose name matches, ignoring case.
+    pub fn take(&mut self, name: &str) -> Option<Item> {
+        let name = name.to_lowercase();
+        let index = self
+            .items
+            .iter()
+            .position(|item| item.name.to_lowercase() == name)?;
+        Some(self.items.remove(index))
+    }
+
+    // Equips a weapon or armour, returning whatever it replaced to the pack.
+    // Anything else is handed back.
+    pub fn equip(&mut self, item: Item) -> Result<(), Item> {
+        let slot = match item.kind {
+            ItemKind::Weapon => &mut self.weapon,
+            ItemKind::Armour => &mut self.armour,
+            ItemKind::Potion => return Err(item),
+        };
+
+        if let Some(old) = slot.take() {
+            self.items.push(old);
+        }
+        *slot = Some(item);
+        Ok(())
+    }
+
+    pub fn unequip(&mut self, kind: ItemKind) -> Option<String> {
+        let slot = match kind {
+            ItemKind::Weapon => &mut self.weapon,
+            ItemKind::Armour => &mut self.armour,
+            ItemKind::Potion => return None,
+        };
+
+        let item = slot.take()?;
+        let name = item.name.clone();
+        self.items.push(item);
+        Some(name)
+    }
+
+    pub fn describe(&self) -> String {
+        let equipped = |slot: &Option<Item>| {
+            slot.as_ref()
+                .map_or("nothing".to_string(), |item| item.summary())
+        };
+
+        let carried = if self.items.is_empty() {
+            "nothing".to_string()
+        } else {
+            self.items
+                .iter()
+                .map(|item| item.summary())
+                .collect::<Vec<String>>()
+                .join(", ")
+        };
+
+        format!(
+            "Weapon: {}\nArmour: {}\nCarrying: {}",
+            equipped(&self.weapon),
+            equipped(&self.armour),
+            carried
+        )
+    }
+}
+
+// Commands any player can send to the server to manage their items.
+pub enum ItemCommand {
+    Inventory,
+    Equip(String),
+    Unequip(ItemKind),
+    Use(String),
+}
+
+const USAGE: &str =
+    "Item commands: /inventory, /equip <item>, /unequip <weapon|armour>, /use <item>";
+
+// Returns None for messages that aren't item commands at all.
+pub fn parse_item_command(text: &str) -> Option<Result<ItemCommand, String>> {
+    let text = text.trim();
+    let (command, args) = match text.find(' ') {
+        Some(at) => (&text[..at], text[at..].trim()),
+        None => (text, ""),
+    };
+
+    let parsed = match command {
+        "/inventory" => Ok(ItemCommand::Inventory),
+        "/equip" if !args.is_empty() => Ok(ItemCommand::Equip(args.to_string())),
+        "/use" if !args.is_empty() => Ok(ItemCommand::Use(args.to_string())),
+        "/unequip" => match args {
+            "weapon" => Ok(ItemCommand::Unequip(ItemKind::Weapon)),
+            "armour" | "armor" => Ok(ItemCommand::Unequip(ItemKind::Armour)),
+            _ => Err(USAGE.to_string()),
+        },
+        "/equip" | "/use" => Err(USAGE.to_string()),
+        _ => return None,
+    };
+
+    Some(parsed)
+}
+
+#[derive(Debug)]
+pub enum ItemLoadError {
+    Io(String, String),
+    Parse(String),
+    BadPotion(String),
+}
+
+impl fmt::Display for ItemLoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ItemLoadError::Io(ref path, ref reason) => {
+                write!(f, "Failed to read item file '{}': {}", path, reason)
+            }
+            ItemLoadError::Parse(ref reason) => write!(f, "Invalid item file: {}", reason),
+            ItemLoadError::BadPotion(ref id) => {
+                write!(f, "Potion '{}' must heal for more than zero.", id)
+            }
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct ItemFile {
+    #[serde(default)]
+    items: HashMap<String, Item>,
+}
+
+pub struct ItemCatalogue {
+    items: HashMap<String, Item>,
+}
+
+impl ItemCatalogue {
+    pub fn get(&self, id: &str) -> Option<&Item> {
+        self.items.get(id)
+    }
+}
+
+pub fn load_items(path: &str) -> Result<ItemCatalogue, ItemLoadError> {
+    let mut contents = String::new();
+    File::open(path)
+        .and_then(|mut file| file.read_to_string(&mut contents))
+        .map_err(|e| ItemLoadError::Io(path.to_string(), e.to_string()))?;
+
+    parse_items(&contents)
+}
+
+pub fn parse_items(contents: &str) -> Result<ItemCatalogue, ItemLoadError> {
+    let item_file: ItemFile =
+        toml::from_str(contents).map_err(|e| ItemLoadError::Parse(e.to_string()))?;
+
+    for (id, item) in item_file.items.iter() {
+        if item.kind == ItemKind::Potion && item.heal <= 0 {
+            return Err(ItemLoadError::BadPotion(id.clone()));
+        }
+    }
+
+    Ok(ItemCatalogue {
+        items: item_file.items,
+    })
+}
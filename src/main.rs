@@ -1,17 +1,38 @@
 extern crate liblurk;
 extern crate rand;
+extern crate rand_chacha;
 extern crate uuid;
 #[macro_use]
 extern crate nickel;
-
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate hmac;
+extern crate pbkdf2;
+extern crate serde_json;
+extern crate sha2;
+extern crate toml;
+
+mod accounts;
+mod admin;
+mod chat;
 mod combat;
+mod config;
 mod entity;
+mod items;
 mod map;
+mod map_file;
+mod monster_catalogue;
 mod monster_spawn;
+mod names;
+mod persistence;
+mod progression;
+mod rate_limit;
 mod rest;
+mod rng;
+mod shop;
 
-use std::collections::HashMap;
-use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::collections::{HashMap, HashSet};
 use std::time::{Duration, Instant};
 
 use uuid::Uuid;
@@ -21,29 +42,38 @@ use liblurk::server::server::{
     LurkServerError, Server, ServerCallbacks, ServerEventContext, UpdateContext,
 };
 
+use accounts::{AccountStore, LoginResult};
+use admin::AdminCommand;
+use chat::{Channel, ChatLimiter};
+use combat::CombatLog;
+use config::{DeathPolicy, ServerConfig};
 use entity::*;
+use items::{Inventory, ItemCommand, ItemKind};
 use map::LootMonsterResult;
+use map::Map;
 use map::MovePlayerResult;
-use map::{Map, MapBuilder};
-use monster_spawn::monster_spawners;
-use monster_spawn::monster_spawners::MolePeopleLevel;
+use monster_catalogue::MonsterCatalogue;
+use persistence::CharacterStore;
+use rand::Rng;
+use rate_limit::{PacketKind, PacketLimiter, RateDecision};
+use rng::GameRng;
+use shop::ShopCommand;
 
 use rest::RestService;
 use std::sync::Arc;
 use std::sync::Mutex;
-use std::thread;
 
-const INITIAL_POINTS: u16 = 600;
-const STAT_LIMIT: u16 = u16::max_value();
+const AUTOSAVE_INTERVAL: Duration = Duration::from_secs(30);
+const COMBAT_LOG_CAPACITY: usize = 50;
 
-const DEFAULT_HEALTH: i16 = 500;
-const DEFAULT_GOLD: u16 = 0;
-
-pub fn get_game_packet() -> Game {
+pub fn get_game_packet(config: &ServerConfig) -> Game {
     Game {
-        initial_points: INITIAL_POINTS,
-        stat_limit: STAT_LIMIT,
-        description: String::from("You find yourself in an uneventful boring dungeon."),
+        initial_points: config.initial_points,
+        stat_limit: config.stat_limit,
+        description: format!(
+            "{}\nNew characters start with {} health and {} gold.",
+            config.description, config.default_health, config.default_gold
+        ),
     }
 }
 
@@ -51,7 +81,22 @@ pub struct Player {
     entity_info: Entity,
     ready: bool,
     started: bool,
+    join_battle: bool,
     id: Uuid,
+    // When the player was first seen dead, for the respawn delay.
+    died_at: Option<Instant>,
+    // Sent ahead of the character to log in to, or claim, its name.
+    password: Option<String>,
+    // Whether the session proved it holds the password for its name, which
+    // admin commands need.
+    authenticated: bool,
+    // When the connection dropped, while the character waits in the world for
+    // its player to come back.
+    disconnected_at: Option<Instant>,
+    // When the client last sent anything, for marking them AFK and removing
+    // them once idle.
+    last_active: Instant,
+    afk: bool,
 }
 
 fn limit_str_len(string: &String) -> String {
@@ -63,11 +108,60 @@ fn limit_str_len(string: &String) -> String {
 }
 
 impl Player {
+    // A session that has yet to send a character.
+    fn new(id: Uuid, base_health: i16) -> Player {
+        Player {
+            entity_info: Entity {
+                update_dirty: false,
+                name: String::new(),
+                attack: 0,
+                defense: 0,
+                regen: 0,
+                health: 0,
+                gold: 0,
+                location: 0,
+                alive: false,
+                monster: false,
+                desc: String::new(),
+                base_health,
+                experience: 0,
+                level: 1,
+                stat_points: 0,
+                inventory: Inventory::default(),
+                roam_chance: 0.0,
+                aggression: Aggression::Never,
+                hostile: false,
+                home: None,
+            },
+            ready: false,
+            started: false,
+            join_battle: true,
+            id,
+            died_at: None,
+            password: None,
+            authenticated: false,
+            disconnected_at: None,
+            last_active: Instant::now(),
+            afk: false,
+        }
+    }
+
+    fn is_linkdead(&self) -> bool {
+        self.disconnected_at.is_some()
+    }
+
     fn get_character_packet(&self) -> Character {
+        let status = if self.is_linkdead() {
+            " [Linkdead]"
+        } else if self.afk {
+            " [AFK]"
+        } else {
+            ""
+        };
         Character::new(
             limit_str_len(&self.entity_info.name),
             self.entity_info.alive,
-            true,
+            self.join_battle,
             false,
             self.started,
             self.ready,
@@ -77,200 +171,331 @@ impl Player {
             self.entity_info.health,
             self.entity_info.gold,
             self.entity_info.location,
-            limit_str_len(&self.entity_info.desc),
+            limit_str_len(&format!(
+                "[Level {}]{} {}",
+                self.entity_info.level, status, self.entity_info.desc
+            )),
         )
         .expect("Invalid character packet from player instance.")
     }
 }
 
+// Everything a player is sent on entering a room: the room itself, its
+// connections and the monsters in it.
+fn room_entry_packets(map: &Map, room_number: u16) -> (Room, Vec<Connection>, Vec<Character>) {
+    let room = map
+        .get_room(&room_number)
+        .expect("Bug: Entering a room that doesn't exist.");
+
+    let room_packet = Room::new(
+        room.get_number(),
+        room.get_name(),
+        limit_str_len(&room.get_description()),
+    )
+    .expect("Bug: Invalid room packet created.");
+
+    let connections = room
+        .get_adjacent_rooms()
+        .iter()
+        .map(|adj_room_num| {
+            let adj_room = map
+                .get_room(&adj_room_num)
+                .expect("Bug: Adjacent room doesn't exist.");
+
+            Connection::new(
+                adj_room.get_number(),
+                adj_room.get_name(),
+                adj_room.get_description(),
+            )
+            .unwrap()
+        })
+        .collect();
+
+    (room_packet, connections, room.get_monster_packets(true))
+}
+
+fn server_message(text: String, receiver: &str) -> Message {
+    Message::new(text, admin::SERVER_NAME.to_string(), receiver.to_string())
+        .expect("Bug: Invalid server message created.")
+}
+
+fn no_such_player(context: &mut ServerEventContext, name: &str) {
+    context.enqueue_message_this(Error::no_target(format!("No player named {}.", name)).unwrap());
+}
+
 struct ExampleServer {
     players: Arc<Mutex<HashMap<Uuid, Player>>>,
     map: Arc<Mutex<Map>>,
+    character_store: Arc<CharacterStore>,
+    accounts: Arc<AccountStore>,
+    combat_log: Arc<Mutex<CombatLog>>,
+    catalogue: MonsterCatalogue,
+    chat_limiter: ChatLimiter,
+    packet_limiter: Arc<Mutex<PacketLimiter>>,
+    // Character names that can't chat.
+    muted: HashSet<String>,
+    config: ServerConfig,
+    rng: GameRng,
     last_update_time: Instant,
+    last_save_time: Instant,
+    last_monster_attack: Instant,
 }
 
 impl ExampleServer {
-    fn new() -> ExampleServer {
-        let mut map_builder = MapBuilder::new();
-
-        let entry_room_id = map_builder.register_room(
-            "Entry Room",
-            "This room seems to be the entrance.",
-            monster_spawners::mean_butler_spawner(),
-        );
-        let basement_id =
-            map_builder.register_room("Basement", "It's very dark, and there seems to be some gross old canned food. It looks like there's a dumbweighter to the attic.", monster_spawners::derry_spawner());
-        let parlor_id = map_builder.register_room(
-            "Parlor",
-            "There's a mess of old furniture and music.",
-            monster_spawners::creepy_uncle_spawner(),
-        );
-        let attic_id =
-            map_builder.register_room("Attic", "Eek! There's some big spiders up here! There seems to be a dumbweighter to the basement.", monster_spawners::spider_spawner());
-
-        let badger_den = map_builder.register_room(
-            "Badger Den",
-            "A honey badger seems to taken refuge here",
-            monster_spawners::honey_badger_spawner(),
-        );
-        let cavern_hall = map_builder.register_room(
-            "Cavern Hall",
-            "A hallway piece of the cavern",
-            monster_spawners::mole_people_spawner(MolePeopleLevel::Low, (3, 5)),
-        );
-        let barracks_north = map_builder.register_room(
-            "Mole Barracks North",
-            "The north wing of the mole barracks.",
-            monster_spawners::mole_people_spawner(MolePeopleLevel::Low, (3, 8)),
-        );
-        let barracks_east = map_builder.register_room(
-            "Mole Barracks East",
-            "The east wing of the mole barracks.",
-            monster_spawners::mole_people_spawner(MolePeopleLevel::Low, (4, 10)),
-        );
-        let barracks_west = map_builder.register_room(
-            "Mole Barracks West",
-            "The west wing of the mole barracks.",
-            monster_spawners::mole_people_spawner(MolePeopleLevel::Low, (5, 10)),
-        );
-        let pit = map_builder.register_room(
-            "Pit",
-            "A dark pit filled with spiders.",
-            monster_spawners::spider_spawner(),
-        );
-        let nursery = map_builder.register_room(
-            "Mole Nursery",
-            "A disgusting nursery cavern filled with mole people.",
-            monster_spawners::mole_people_spawner(MolePeopleLevel::Low, (15, 20)),
-        );
-        let cache = map_builder.register_room(
-            "Cache",
-            "A cavern serving as a food cache.",
-            monster_spawners::mole_people_spawner(MolePeopleLevel::Mid, (5, 8)),
-        );
-        let cavern = map_builder.register_room(
-            "Cavern",
-            "A cavern filled with mole people.",
-            monster_spawners::mole_people_spawner(MolePeopleLevel::Mid, (15, 20)),
-        );
-        let deep_cavern = map_builder.register_room(
-            "Deep Cavern",
-            "Another deeper cavern filled with yet more mole people.",
-            monster_spawners::composite_spawner(vec![
-                monster_spawners::mole_people_spawner(MolePeopleLevel::Mid, (15, 20)),
-                monster_spawners::mole_high_priest_spawner(),
-            ]),
-        );
-        let mole_grounds = map_builder.register_room(
-            "Mole Grounds",
-            "A large living area of the mole people.",
-            monster_spawners::mole_people_spawner(MolePeopleLevel::Hard, (15, 20)),
-        );
-        let spawn_grounds = map_builder.register_room(
-            "Spawn Grounds",
-            "A spawning grounds where the queens spawn more moles.",
-            monster_spawners::composite_spawner(vec![
-                monster_spawners::mole_queen_spawner(),
-                monster_spawners::mole_people_spawner(MolePeopleLevel::Hard, (25, 30)),
-            ]),
-        );
-        let mole_pit_west = map_builder.register_room(
-            "Mole Pit West",
-            "The west mole pit.",
-            monster_spawners::mole_people_spawner(MolePeopleLevel::Hard, (10, 12)),
-        );
-        let mole_pit_east = map_builder.register_room(
-            "Mole Pit East",
-            "The east mole pit.",
-            monster_spawners::mole_people_spawner(MolePeopleLevel::Hard, (10, 12)),
-        );
-        let mole_pit_south = map_builder.register_room(
-            "Mole Pit South",
-            "The south mole pit.",
-            monster_spawners::mole_people_spawner(MolePeopleLevel::Hard, (10, 12)),
-        );
-        let temple = map_builder.register_room(
-            "Mole Temple",
-            "A cavern temple it seems.",
-            monster_spawners::composite_spawner(vec![
-                monster_spawners::mole_high_priest_spawner(),
-                monster_spawners::mole_people_spawner(MolePeopleLevel::Hard, (30, 35)),
-            ]),
-        );
-        let goliath_gate = map_builder.register_room(
-            "Goliath Gate",
-            "The goliath guards here!",
-            monster_spawners::composite_spawner(vec![
-                monster_spawners::great_mole_goliath_spawner(),
-                monster_spawners::mole_people_spawner(MolePeopleLevel::Hard, (5, 10)),
-            ]),
-        );
-        let pit_of_queens = map_builder.register_room(
-            "Pit of Queens",
-            "The queens rule from here.",
-            monster_spawners::composite_spawner(vec![
-                monster_spawners::pit_of_queens_spawner(),
-                monster_spawners::mole_people_spawner(MolePeopleLevel::Mid, (40, 50)),
-            ]),
-        );
-        let cavern_end = map_builder.register_room(
-            "Cavern End",
-            "This is the end, the smell is awful.",
-            monster_spawners::homonculus_spawner(),
-        );
-
-        map_builder
-            .link_rooms(entry_room_id, parlor_id)
-            .expect("Failed to link entry room and parlor.");
-        map_builder
-            .link_rooms(parlor_id, attic_id)
-            .expect("Failed to link parlor and attic.");
-        map_builder
-            .link_rooms(parlor_id, basement_id)
-            .expect("Failed to link parlor and basement.");
-        map_builder
-            .link_rooms(attic_id, basement_id)
-            .expect("Failed to link attic and basement.");
-
-        map_builder.link_rooms(basement_id, badger_den).unwrap();
-        map_builder.link_rooms(badger_den, cavern_hall).unwrap();
-        map_builder.link_rooms(badger_den, barracks_north).unwrap();
-        map_builder.link_rooms(badger_den, cavern).unwrap();
-        map_builder
-            .link_rooms(barracks_north, barracks_east)
-            .unwrap();
-        map_builder
-            .link_rooms(barracks_north, barracks_west)
-            .unwrap();
-        map_builder
-            .link_rooms(barracks_east, barracks_west)
-            .unwrap();
-        map_builder.link_rooms(cavern_hall, pit).unwrap();
-        map_builder.link_rooms(cavern_hall, nursery).unwrap();
-        map_builder.link_rooms(cavern_hall, cache).unwrap();
-        map_builder.link_rooms(cavern, deep_cavern).unwrap();
-        map_builder.link_rooms(deep_cavern, mole_grounds).unwrap();
-        map_builder.link_rooms(mole_grounds, spawn_grounds).unwrap();
-        map_builder.link_rooms(mole_grounds, mole_pit_west).unwrap();
-        map_builder.link_rooms(mole_grounds, mole_pit_east).unwrap();
-        map_builder
-            .link_rooms(mole_grounds, mole_pit_south)
-            .unwrap();
-        map_builder.link_rooms(mole_grounds, temple).unwrap();
-        map_builder.link_rooms(temple, goliath_gate).unwrap();
-        map_builder.link_rooms(goliath_gate, pit_of_queens).unwrap();
-        map_builder.link_rooms(pit_of_queens, cavern_end).unwrap();
-
-        map_builder
-            .set_start_room(entry_room_id)
-            .expect("Failed to set starting room.");
-
-        let map = map_builder.complete().expect("Failed to build map");
-
+    fn new(
+        map: Map,
+        catalogue: MonsterCatalogue,
+        character_store: CharacterStore,
+        accounts: AccountStore,
+        config: ServerConfig,
+        rng: GameRng,
+    ) -> ExampleServer {
         ExampleServer {
             players: Arc::new(Mutex::new(HashMap::new())),
             map: Arc::new(Mutex::new(map)),
+            character_store: Arc::new(character_store),
+            accounts: Arc::new(accounts),
+            combat_log: Arc::new(Mutex::new(CombatLog::new(COMBAT_LOG_CAPACITY))),
+            catalogue,
+            chat_limiter: ChatLimiter::new(
+                config.chat_window(),
+                config.room_chat_limit,
+                config.all_chat_limit,
+            ),
+            packet_limiter: Arc::new(Mutex::new(PacketLimiter::new(
+                [
+                    PacketKind::Fight,
+                    PacketKind::Loot,
+                    PacketKind::Message,
+                    PacketKind::ChangeRoom,
+                    PacketKind::Character,
+                    PacketKind::Start,
+                ]
+                .iter()
+                .map(|&kind| (kind, config.packet_limit(kind)))
+                .collect(),
+                config.rate_limit_strikes,
+            ))),
+            muted: config.muted.iter().cloned().collect(),
+            config,
+            rng,
             last_update_time: Instant::now(),
+            last_save_time: Instant::now(),
+            last_monster_attack: Instant::now(),
+        }
+    }
+
+    // Only players who have had a character accepted have anything to save.
+    fn save_players<'a, I: Iterator<Item = &'a Player>>(&self, players: I) {
+        let entities: Vec<&Entity> = players
+            .filter(|player| player.ready)
+            .map(|player| &player.entity_info)
+            .collect();
+
+        if entities.is_empty() {
+            return;
+        }
+
+        if let Err(e) = self.character_store.save_characters(&entities) {
+            println!("Failed to save characters: {}", e);
+        }
+    }
+
+    // Each hostile monster attacks a random living player in its room. Players
+    // are safe in a sanctuary.
+    fn update_aggression(&mut self, context: &UpdateContext, players: &mut HashMap<Uuid, Player>) {
+        let mut map = self.map.lock().unwrap();
+
+        for room_number in map.get_room_numbers() {
+            let room = map.get_room_mut(&room_number).unwrap();
+            if room.is_sanctuary() {
+                continue;
+            }
+
+            // Sorted by name, which unlike the client id is the same when a
+            // seed is replayed.
+            let mut room_player_ids = room.get_player_ids();
+            room_player_ids.retain(|id| players.contains_key(id));
+            room_player_ids.sort_by(|a, b| {
+                players[a]
+                    .entity_info
+                    .name
+                    .cmp(&players[b].entity_info.name)
+            });
+            let mut events = vec![];
+            for monster in room.get_alive_monsters_mut() {
+                if !monster.hostile {
+                    continue;
+                }
+
+                // Linkdead and AFK players can't fight back, so are left alone.
+                let targets: Vec<Uuid> = room_player_ids
+                    .iter()
+                    .filter(|id| {
+                        players.get(id).map_or(false, |player| {
+                            player.started
+                                && player.entity_info.alive
+                                && !player.is_linkdead()
+                                && !player.afk
+                        })
+                    })
+                    .cloned()
+                    .collect();
+                if targets.is_empty() {
+                    break;
+                }
+
+                let target = targets[self.rng.gen_range(0, targets.len())];
+                let player = players.get_mut(&target).unwrap();
+                events.extend(combat::handle_fight(
+                    monster,
+                    &mut player.entity_info,
+                    &mut self.rng,
+                ));
+            }
+
+            if events.is_empty() {
+                continue;
+            }
+
+            let message = combat::render_events(&events);
+            self.combat_log.lock().unwrap().record(room_number, events);
+
+            for send_target in room.get_player_ids() {
+                for player_id in room.get_player_ids() {
+                    if let Some(player) = players.get(&player_id) {
+                        context.enqueue_message(player.get_character_packet(), send_target);
+                    }
+                }
+                for monster in room.get_monster_packets(false) {
+                    context.enqueue_message(monster, send_target);
+                }
+                if let Some(player) = players.get(&send_target) {
+                    context.enqueue_message(
+                        server_message(message.clone(), &player.entity_info.name),
+                        send_target,
+                    );
+                }
+            }
+        }
+    }
+
+    // Dead players lie where they fell for the respawn delay, lootable, and are
+    // then either brought back at the start room or, with permadeath, deleted.
+    fn update_deaths(
+        &self,
+        context: &UpdateContext,
+        players: &mut HashMap<Uuid, Player>,
+        now: Instant,
+    ) {
+        let mut expired = vec![];
+
+        for player in players.values_mut() {
+            if !player.started || player.entity_info.alive {
+                player.died_at = None;
+                continue;
+            }
+
+            match player.died_at {
+                None => {
+                    player.died_at = Some(now);
+                    let text = match self.config.death_policy {
+                        DeathPolicy::Respawn => format!(
+                            "You have died. You will respawn in {} seconds.",
+                            self.config.respawn_delay
+                        ),
+                        DeathPolicy::Permadeath => format!(
+                            "You have died. Your character will be lost in {} seconds.",
+                            self.config.respawn_delay
+                        ),
+                    };
+                    context
+                        .enqueue_message(server_message(text, &player.entity_info.name), player.id);
+                }
+                Some(died_at) if now.duration_since(died_at) >= self.config.respawn_delay() => {
+                    expired.push(player.id);
+                }
+                _ => {}
+            }
+        }
+
+        let mut map = self.map.lock().unwrap();
+
+        for id in expired {
+            let player = players.get_mut(&id).unwrap();
+            let name = player.entity_info.name.clone();
+            let old_room_ids = map
+                .get_player_room(&id)
+                .map(|room| room.get_player_ids())
+                .unwrap_or_default();
+
+            match self.config.death_policy {
+                DeathPolicy::Respawn => {
+                    let start_room = map.get_start_room().get_number();
+                    map.move_player(&id, start_room);
+
+                    let penalty = (player.entity_info.gold as u32
+                        * self.config.death_gold_penalty as u32
+                        / 100) as u16;
+                    player.entity_info.gold -= penalty;
+                    player.entity_info.health = player.entity_info.get_max_health();
+                    player.entity_info.alive = true;
+                    player.entity_info.location = start_room;
+                    player.entity_info.update_dirty = true;
+                    player.died_at = None;
+
+                    for player_id in old_room_ids {
+                        context.enqueue_message(player.get_character_packet(), player_id);
+                    }
+
+                    let (room_packet, connections, monsters) = room_entry_packets(&map, start_room);
+                    context.enqueue_message(room_packet, id);
+                    for connection in connections {
+                        context.enqueue_message(connection, id);
+                    }
+                    for monster_packet in monsters {
+                        context.enqueue_message(monster_packet, id);
+                    }
+
+                    context.enqueue_message(
+                        server_message(
+                            format!(
+                                "You have respawned in {}, losing {} gold.",
+                                map.get_start_room().get_name(),
+                                penalty
+                            ),
+                            &name,
+                        ),
+                        id,
+                    );
+                }
+                DeathPolicy::Permadeath => {
+                    println!("{} has died for good.", name);
+                    if let Err(e) = self.character_store.remove(&name) {
+                        println!("Failed to delete character {}: {}", name, e);
+                    }
+
+                    // Like a looted monster, the body is sent out of the world.
+                    map.clear_player(&id);
+                    player.entity_info.location = std::u16::MAX;
+                    for player_id in old_room_ids.into_iter().filter(|other| *other != id) {
+                        context.enqueue_message(player.get_character_packet(), player_id);
+                    }
+
+                    context.enqueue_message(
+                        server_message(
+                            "Your character has died for good. Send a new character to play again."
+                                .to_string(),
+                            &name,
+                        ),
+                        id,
+                    );
+                    // A linkdead session still goes once its grace runs out.
+                    let disconnected_at = player.disconnected_at;
+                    *player = Player::new(id, self.config.default_health);
+                    player.disconnected_at = disconnected_at;
+                }
+            }
         }
     }
 
@@ -283,6 +508,894 @@ impl ExampleServer {
         None
     }
 
+    // Why the session can't take a name, if it can't. A saved character's
+    // own name is fine, that's how it is played again.
+    fn check_name(&self, client_id: &Uuid, name: &str) -> Result<(), Error> {
+        if let Err(reason) = names::check_format(name) {
+            return Err(Error::other(reason).unwrap());
+        }
+
+        if names::is_reserved(name) {
+            return Err(Error::player_exists(format!("{} is a reserved name.", name)).unwrap());
+        }
+
+        if self.is_unclaimed_admin(name) {
+            return Err(Error::player_exists(format!(
+                "{} is reserved for an administrator.",
+                name
+            ))
+            .unwrap());
+        }
+
+        if self.catalogue.is_monster_name(name) || self.map.lock().unwrap().is_npc_name(name) {
+            return Err(Error::player_exists(format!(
+                "{} is already the name of a monster.",
+                name
+            ))
+            .unwrap());
+        }
+
+        let playing = self.players.lock().unwrap().values().any(|player| {
+            player.id != *client_id
+                && player.ready
+                && !player.is_linkdead()
+                && player.entity_info.name.eq_ignore_ascii_case(name)
+        });
+        if playing {
+            return Err(Error::player_exists(format!("{} is already playing.", name)).unwrap());
+        }
+
+        match self.character_store.find_name(name) {
+            Some(ref saved) if saved != name => Err(Error::player_exists(format!(
+                "The name {} is too close to the existing character {}.",
+                name, saved
+            ))
+            .unwrap()),
+            _ => Ok(()),
+        }
+    }
+
+    // Logs the session in to a claimed name. Returns whether the name is free
+    // and the session sent a password for it, in which case the password is
+    // left pending for claim_name once the character is accepted.
+    fn authenticate(&self, client_id: &Uuid, name: &str) -> Result<bool, Error> {
+        let password = self
+            .players
+            .lock()
+            .unwrap()
+            .get_mut(client_id)
+            .and_then(|player| player.password.take());
+
+        let password = match password {
+            Some(password) => password,
+            None if self.accounts.has_account(name) => {
+                return Err(Error::other(format!(
+                    "{} is protected by a password. Send /password <password> to Server first.",
+                    name
+                ))
+                .unwrap());
+            }
+            None if self.config.require_password => {
+                return Err(Error::other(
+                    "Send /password <password> to Server to claim a name first.".to_string(),
+                )
+                .unwrap());
+            }
+            None => return Ok(false),
+        };
+
+        match self.accounts.login(name, &password, Instant::now()) {
+            LoginResult::Success => {
+                self.mark_authenticated(client_id);
+                Ok(false)
+            }
+            LoginResult::NoAccount => {
+                if let Some(player) = self.players.lock().unwrap().get_mut(client_id) {
+                    player.password = Some(password);
+                }
+                Ok(true)
+            }
+            LoginResult::WrongPassword => {
+                Err(Error::other(format!("Wrong password for {}.", name)).unwrap())
+            }
+            LoginResult::LockedOut(remaining) => Err(Error::other(format!(
+                "Too many failed logins for {}. Try again in {} seconds.",
+                name,
+                remaining.as_secs() + 1
+            ))
+            .unwrap()),
+        }
+    }
+
+    // Gives the player's name the password it sent, now the character has
+    // been accepted.
+    fn claim_name(&self, player: &mut Player, name: &str) -> Result<(), Error> {
+        let password = match player.password.take() {
+            Some(password) => password,
+            None => return Ok(()),
+        };
+
+        self.accounts.set_password(name, &password).map_err(|e| {
+            println!("Failed to save account {}: {}", name, e);
+            Error::other("Failed to save your password.".to_string()).unwrap()
+        })?;
+        println!("{} claimed a name.", name);
+        player.authenticated = true;
+        Ok(())
+    }
+
+    // Admin names are claimed before they are listed in the config, so nobody
+    // else can take one and its rights by being first to set a password.
+    fn is_unclaimed_admin(&self, name: &str) -> bool {
+        self.config.admins.iter().any(|admin| admin == name) && !self.accounts.has_account(name)
+    }
+
+    fn mark_authenticated(&self, client_id: &Uuid) {
+        if let Some(player) = self.players.lock().unwrap().get_mut(client_id) {
+            player.authenticated = true;
+        }
+    }
+
+    // Hands a linkdead character back to the session logging in as it, and
+    // resyncs the session with where the character is. Returns whether there
+    // was one to hand back.
+    fn reconnect(&self, context: &mut ServerEventContext, name: &str) -> bool {
+        let mut players = self.players.lock().unwrap();
+        let old_id = match players
+            .values()
+            .find(|player| player.is_linkdead() && player.entity_info.name == name)
+        {
+            Some(player) => player.id,
+            None => return false,
+        };
+
+        let new_id = context.get_client_id();
+        let (authenticated, password) = players.get_mut(&new_id).map_or((false, None), |session| {
+            (session.authenticated, session.password.take())
+        });
+        let mut player = players.remove(&old_id).unwrap();
+        player.id = new_id;
+        player.authenticated = authenticated;
+        player.password = password;
+        player.disconnected_at = None;
+        player.last_active = Instant::now();
+        player.afk = false;
+        player.entity_info.update_dirty = true;
+        println!("{} reconnected.", name);
+
+        let mut map = self.map.lock().unwrap();
+        map.rebind_player(&old_id, &new_id);
+
+        context.enqueue_message_this(Accept::new(CHARACTER_TYPE));
+        context.enqueue_message_this(player.get_character_packet());
+
+        let (room_packet, connections, monsters) =
+            room_entry_packets(&map, player.entity_info.location);
+        context.enqueue_message_this(room_packet);
+        for connection in connections {
+            context.enqueue_message_this(connection);
+        }
+        for other_id in map.get_player_room(&new_id).unwrap().get_player_ids() {
+            if let Some(other) = players.get(&other_id) {
+                context.enqueue_message_this(other.get_character_packet());
+            }
+        }
+        for monster_packet in monsters {
+            context.enqueue_message_this(monster_packet);
+        }
+
+        players.insert(new_id, player);
+        true
+    }
+
+    // Whether the client may send another packet of the kind right now.
+    // Clients that keep at it while limited are dropped from the game.
+    fn check_rate(&mut self, context: &mut ServerEventContext, kind: PacketKind) -> bool {
+        let client_id = context.get_client_id();
+        let decision = self
+            .packet_limiter
+            .lock()
+            .unwrap()
+            .check(client_id, kind, Instant::now());
+
+        match decision {
+            RateDecision::Allowed => true,
+            RateDecision::Limited => {
+                context.enqueue_message_this(
+                    Error::other(format!(
+                        "You are sending {} packets too quickly.",
+                        kind.name()
+                    ))
+                    .unwrap(),
+                );
+                false
+            }
+            RateDecision::Dropped => {
+                context.enqueue_message_this(
+                    Error::other(
+                        "You have been removed from the game for flooding the server.".to_string(),
+                    )
+                    .unwrap(),
+                );
+
+                // As with a kick the connection can't be closed from here, so
+                // the limiter refuses every packet the client sends from now
+                // on, including new characters, until it disconnects.
+                if self.players.lock().unwrap().contains_key(&client_id) {
+                    println!("Dropping client {} for flooding.", client_id);
+                    self.remove_player(&client_id);
+                }
+                false
+            }
+            RateDecision::Refused => {
+                context.enqueue_message_this(
+                    Error::other("You have been removed from the game.".to_string()).unwrap(),
+                );
+                false
+            }
+        }
+    }
+
+    // Any packet from the client counts as activity.
+    fn touch(&self, client_id: &Uuid) {
+        if let Some(player) = self.players.lock().unwrap().get_mut(client_id) {
+            player.last_active = Instant::now();
+            if player.afk {
+                player.afk = false;
+                player.entity_info.update_dirty = true;
+            }
+        }
+    }
+
+    // Players who stop sending packets are marked AFK for others to see, and
+    // later saved and taken out of the world. Linkdead players have their own
+    // grace period.
+    fn update_idle(
+        &mut self,
+        context: &UpdateContext,
+        players: &mut HashMap<Uuid, Player>,
+        now: Instant,
+    ) {
+        let mut idle = vec![];
+
+        for player in players.values_mut() {
+            if !player.started || !player.entity_info.alive || player.is_linkdead() {
+                continue;
+            }
+
+            let inactive = now.duration_since(player.last_active);
+            if self.config.idle_timeout > 0 && inactive >= self.config.idle_timeout() {
+                idle.push(player.id);
+            } else if self.config.afk_timeout > 0
+                && inactive >= self.config.afk_timeout()
+                && !player.afk
+            {
+                player.afk = true;
+                player.entity_info.update_dirty = true;
+                context.enqueue_message(
+                    server_message(
+                        "You are now marked as AFK.".to_string(),
+                        &player.entity_info.name,
+                    ),
+                    player.id,
+                );
+            }
+        }
+
+        for id in idle {
+            // The connection stays open, so the session starts over and the
+            // player can send their character again to carry on.
+            let player = std::mem::replace(
+                players.get_mut(&id).unwrap(),
+                Player::new(id, self.config.default_health),
+            );
+            println!("{} was idle for too long.", player.entity_info.name);
+            context.enqueue_message(
+                server_message(
+                    "You were idle for too long, so your character was saved and removed."
+                        .to_string(),
+                    &player.entity_info.name,
+                ),
+                id,
+            );
+            self.chat_limiter.forget(&id);
+            self.release_player(&player);
+            self.map.lock().unwrap().clear_player(&id);
+        }
+    }
+
+    // Characters whose players didn't come back in time leave the world.
+    fn update_linkdead(&self, players: &mut HashMap<Uuid, Player>, now: Instant) {
+        let grace = self.config.reconnect_grace();
+        let expired: Vec<Uuid> = players
+            .values()
+            .filter(|player| {
+                player
+                    .disconnected_at
+                    .map_or(false, |at| now.duration_since(at) >= grace)
+            })
+            .map(|player| player.id)
+            .collect();
+
+        for id in expired {
+            let player = players.remove(&id).unwrap();
+            println!("{} never reconnected.", player.entity_info.name);
+            self.release_player(&player);
+            self.map.lock().unwrap().clear_player(&id);
+        }
+    }
+
+    // Before a character is accepted the password is kept for logging in;
+    // afterwards it sets the character's password.
+    fn on_password_command(
+        &mut self,
+        context: &mut ServerEventContext,
+        message: &Message,
+        password: String,
+    ) -> LurkServerError {
+        let mut players = self.players.lock().unwrap();
+        let player = match players.get_mut(&context.get_client_id()) {
+            Some(player) => player,
+            None => return Ok(()),
+        };
+
+        let reply = if player.ready {
+            let name = &player.entity_info.name;
+            if self.is_unclaimed_admin(name) {
+                context.enqueue_message_this(
+                    Error::other(format!("{} is reserved for an administrator.", name)).unwrap(),
+                );
+                return Ok(());
+            }
+            if let Err(e) = self.accounts.set_password(name, &password) {
+                println!("Failed to save account {}: {}", name, e);
+                context.enqueue_message_this(
+                    Error::other("Failed to save your password.".to_string()).unwrap(),
+                );
+                return Ok(());
+            }
+            player.authenticated = true;
+            format!("Password set for {}.", name)
+        } else {
+            player.password = Some(password);
+            "Password noted, now send your character.".to_string()
+        };
+
+        context.enqueue_message_this(server_message(reply, &message.sender));
+        Ok(())
+    }
+
+    // Item commands are open to everyone, anything else is an admin command.
+    fn on_server_message(
+        &mut self,
+        context: &mut ServerEventContext,
+        message: &Message,
+    ) -> LurkServerError {
+        if let Some(password) = accounts::parse_password_command(&message.message) {
+            return self.on_password_command(context, message, password);
+        }
+
+        // The sender is taken from the session, not from the packet.
+        let (sender_name, authenticated) =
+            match self.players.lock().unwrap().get(&context.get_client_id()) {
+                Some(player) if player.ready => {
+                    (player.entity_info.name.clone(), player.authenticated)
+                }
+                _ => {
+                    context.enqueue_message_this(
+                        Error::not_ready("Create a character before sending commands.".to_string())
+                            .unwrap(),
+                    );
+                    return Ok(());
+                }
+            };
+
+        if let Some(parsed) = items::parse_item_command(&message.message) {
+            match parsed {
+                Ok(command) => self.run_item_command(context, &sender_name, command),
+                Err(usage) => context.enqueue_message_this(Error::other(usage).unwrap()),
+            }
+            return Ok(());
+        }
+
+        if !self.config.admins.contains(&sender_name) {
+            context.enqueue_message_this(
+                Error::other("Only administrators can send that command.".to_string()).unwrap(),
+            );
+            return Ok(());
+        }
+
+        // An admin's name is only worth anything once it is claimed, or anyone
+        // could connect under it.
+        if !authenticated || !self.accounts.has_account(&sender_name) {
+            context.enqueue_message_this(
+                Error::other(
+                    "Administrators must log in with a password to send commands.".to_string(),
+                )
+                .unwrap(),
+            );
+            return Ok(());
+        }
+
+        match admin::parse_command(&message.message) {
+            Ok(command) => {
+                println!("Admin {} issued: {}", sender_name, message.message);
+                self.run_admin_command(context, &sender_name, command);
+            }
+            Err(usage) => {
+                context.enqueue_message_this(Error::other(usage).unwrap());
+            }
+        }
+
+        Ok(())
+    }
+
+    fn on_channel_message(
+        &mut self,
+        context: &mut ServerEventContext,
+        channel: Channel,
+        message: &Message,
+    ) -> LurkServerError {
+        let players = self.players.lock().unwrap();
+
+        let sender = match players.get(&context.get_client_id()) {
+            Some(player) if player.started => player,
+            _ => {
+                context.enqueue_message_this(
+                    Error::not_ready("You have not started yet.".to_string()).unwrap(),
+                );
+                return Ok(());
+            }
+        };
+
+        if !self
+            .chat_limiter
+            .try_send(sender.id, channel, Instant::now())
+        {
+            context.enqueue_message_this(
+                Error::other(format!(
+                    "You are sending messages to {} too quickly.",
+                    channel.name()
+                ))
+                .unwrap(),
+            );
+            return Ok(());
+        }
+
+        let recipients: Vec<Uuid> = match channel {
+            Channel::Room => match self.map.lock().unwrap().get_player_room(&sender.id) {
+                Some(room) => room.get_player_ids(),
+                None => vec![],
+            },
+            Channel::All => players
+                .values()
+                .filter(|player| player.started)
+                .map(|player| player.id)
+                .collect(),
+        };
+
+        // The sender is filled in from the session so it can't be spoofed.
+        let channel_message = Message::new(
+            message.message.clone(),
+            sender.entity_info.name.clone(),
+            channel.name().to_string(),
+        )
+        .expect("Bug: Invalid channel message created.");
+
+        for recipient in recipients.into_iter().filter(|id| *id != sender.id) {
+            context.enqueue_message(channel_message.clone(), recipient);
+        }
+
+        Ok(())
+    }
+
+    // Trades with the shopkeeper in the sender's room.
+    fn on_shop_message(
+        &mut self,
+        context: &mut ServerEventContext,
+        message: &Message,
+    ) -> LurkServerError {
+        let mut players = self.players.lock().unwrap();
+        let mut map = self.map.lock().unwrap();
+
+        let player = match players.get_mut(&context.get_client_id()) {
+            Some(player) if player.started => player,
+            _ => {
+                context.enqueue_message_this(
+                    Error::not_ready("You have not started yet.".to_string()).unwrap(),
+                );
+                return Ok(());
+            }
+        };
+
+        let shop = match map
+            .get_player_room_mut(&player.id)
+            .and_then(|room| room.get_shop_mut())
+        {
+            Some(shop) => shop,
+            None => {
+                context.enqueue_message_this(
+                    Error::no_target("There is no shop here.".to_string()).unwrap(),
+                );
+                return Ok(());
+            }
+        };
+
+        let reply = match shop::parse_shop_command(&message.message) {
+            Ok(ShopCommand::List) => shop.describe_wares(),
+            Ok(ShopCommand::Buy(name)) => {
+                if !player.entity_info.alive {
+                    context.enqueue_message_this(
+                        Error::other("The dead cannot trade.".to_string()).unwrap(),
+                    );
+                    return Ok(());
+                }
+
+                match shop.buy(&name, &mut player.entity_info) {
+                    Ok(reply) => reply,
+                    Err(reason) => {
+                        context.enqueue_message_this(Error::other(reason).unwrap());
+                        return Ok(());
+                    }
+                }
+            }
+            Err(usage) => {
+                context.enqueue_message_this(Error::other(usage).unwrap());
+                return Ok(());
+            }
+        };
+
+        context.enqueue_message_this(
+            Message::new(reply, shop.keeper.clone(), player.entity_info.name.clone())
+                .expect("Bug: Invalid shopkeeper message created."),
+        );
+
+        // The buyer sees their new gold and stats straight away, everyone
+        // else on the next update.
+        context.enqueue_message_this(player.get_character_packet());
+
+        Ok(())
+    }
+
+    // Once started, the stats in a character packet are the totals the player
+    // wants, paid for with points earned by leveling. Clients tend to resend
+    // their stats as they are, which only changes the join battle flag.
+    fn update_started_character(
+        &mut self,
+        context: &mut ServerEventContext,
+        character: &Character,
+    ) -> LurkServerError {
+        let mut players = self.players.lock().unwrap();
+        let player = match players.get_mut(&context.get_client_id()) {
+            Some(player) => player,
+            None => return Ok(()),
+        };
+
+        if let Err(reason) = progression::raise_stats(
+            &mut player.entity_info,
+            character.attack,
+            character.defense,
+            character.regeneration,
+            self.config.stat_limit,
+        ) {
+            context.enqueue_message_this(Error::stat_error(reason).unwrap());
+            return Ok(());
+        }
+
+        player.join_battle = character.join_battle;
+        context.enqueue_message_this(Accept::new(CHARACTER_TYPE));
+        context.enqueue_message_this(player.get_character_packet());
+        Ok(())
+    }
+
+    fn run_item_command(
+        &mut self,
+        context: &mut ServerEventContext,
+        player_name: &String,
+        command: ItemCommand,
+    ) {
+        let mut players = self.players.lock().unwrap();
+        let player = match players.get_mut(&context.get_client_id()) {
+            Some(player) if player.started => player,
+            _ => {
+                context.enqueue_message_this(
+                    Error::not_ready("You have not started yet.".to_string()).unwrap(),
+                );
+                return;
+            }
+        };
+
+        let entity = &mut player.entity_info;
+        let reply = match command {
+            ItemCommand::Inventory => entity.inventory.describe(),
+            ItemCommand::Equip(name) => {
+                let item = match entity.inventory.take(&name) {
+                    Some(item) => item,
+                    None => {
+                        context.enqueue_message_this(
+                            Error::no_target(format!("You aren't carrying {}.", name)).unwrap(),
+                        );
+                        return;
+                    }
+                };
+
+                let item_name = item.name.clone();
+                if let Err(item) = entity.inventory.equip(item) {
+                    entity.inventory.items.push(item);
+                    context.enqueue_message_this(
+                        Error::other(format!("{} can't be equipped.", item_name)).unwrap(),
+                    );
+                    return;
+                }
+
+                // Equipment changes the stats shown to everyone in the room.
+                entity.update_dirty = true;
+                format!("You equip the {}.", item_name)
+            }
+            ItemCommand::Unequip(kind) => match entity.inventory.unequip(kind) {
+                Some(item_name) => {
+                    entity.update_dirty = true;
+                    format!("You put away the {}.", item_name)
+                }
+                None => {
+                    context.enqueue_message_this(
+                        Error::other(format!("You have no {} equipped.", kind.name())).unwrap(),
+                    );
+                    return;
+                }
+            },
+            ItemCommand::Use(name) => {
+                if !entity.alive {
+                    context.enqueue_message_this(
+                        Error::other("The dead cannot use items.".to_string()).unwrap(),
+                    );
+                    return;
+                }
+
+                let item = match entity.inventory.take(&name) {
+                    Some(item) => item,
+                    None => {
+                        context.enqueue_message_this(
+                            Error::no_target(format!("You aren't carrying {}.", name)).unwrap(),
+                        );
+                        return;
+                    }
+                };
+
+                if item.kind != ItemKind::Potion {
+                    context.enqueue_message_this(
+                        Error::other(format!("{} can't be used, try /equip.", item.name)).unwrap(),
+                    );
+                    entity.inventory.items.push(item);
+                    return;
+                }
+
+                let old_health = entity.health;
+                entity.health =
+                    (entity.health.saturating_add(item.heal)).min(entity.get_max_health());
+                entity.update_dirty = true;
+                format!(
+                    "You use the {} and recover {} health.",
+                    item.name,
+                    entity.health - old_health
+                )
+            }
+        };
+
+        context.enqueue_message_this(server_message(reply, player_name));
+    }
+
+    fn run_admin_command(
+        &mut self,
+        context: &mut ServerEventContext,
+        admin_name: &String,
+        command: AdminCommand,
+    ) {
+        let reply = match command {
+            AdminCommand::Kick(name) => {
+                let target_id = match self.get_player_id_by_name(&name) {
+                    Some(id) => id,
+                    None => return no_such_player(context, &name),
+                };
+
+                if target_id == context.get_client_id() {
+                    context.enqueue_message_this(
+                        Error::other("You cannot kick yourself.".to_string()).unwrap(),
+                    );
+                    return;
+                }
+
+                context.enqueue_message(
+                    server_message(
+                        "You have been removed from the game by an administrator.".to_string(),
+                        &name,
+                    ),
+                    target_id,
+                );
+
+                // There is no way to close the connection from here, so the
+                // player is dropped from the game as if they had left, and the
+                // limiter refuses anything else the client sends. Otherwise it
+                // could send its character again and take it straight back.
+                self.packet_limiter.lock().unwrap().block(target_id);
+                self.remove_player(&target_id);
+                format!("Kicked {}.", name)
+            }
+            AdminCommand::Teleport(name, room_number) => {
+                let target_id = match self.get_player_id_by_name(&name) {
+                    Some(id) => id,
+                    None => return no_such_player(context, &name),
+                };
+
+                let mut players = self.players.lock().unwrap();
+                let mut map = self.map.lock().unwrap();
+                let player = players.get_mut(&target_id).unwrap();
+
+                if !player.started {
+                    context.enqueue_message_this(
+                        Error::not_ready(format!("{} has not started yet.", name)).unwrap(),
+                    );
+                    return;
+                }
+
+                let old_room_id = player.entity_info.location;
+
+                match map.move_player(&target_id, room_number) {
+                    MovePlayerResult::Success => {}
+                    _ => {
+                        context.enqueue_message_this(
+                            Error::bad_room("Room does not exist.".to_string()).unwrap(),
+                        );
+                        return;
+                    }
+                }
+
+                player.entity_info.location = room_number;
+
+                if let Some(old_room) = map.get_room(&old_room_id) {
+                    for player_id in old_room.get_player_ids() {
+                        context.enqueue_message(player.get_character_packet(), player_id);
+                    }
+                }
+
+                let (room_packet, connections, monsters) = room_entry_packets(&map, room_number);
+                context.enqueue_message(room_packet, target_id);
+                for connection in connections {
+                    context.enqueue_message(connection, target_id);
+                }
+
+                let room = map.get_room(&room_number).unwrap();
+                for player_id in room.get_player_ids() {
+                    context.enqueue_message(player.get_character_packet(), player_id);
+                }
+
+                for monster_packet in monsters {
+                    context.enqueue_message(monster_packet, target_id);
+                }
+
+                format!("Teleported {} to {}.", name, room.get_name())
+            }
+            AdminCommand::Spawn(room_number, monster_id) => {
+                let template = match self.catalogue.template(&monster_id) {
+                    Some(template) => template.clone(),
+                    None => {
+                        context.enqueue_message_this(
+                            Error::no_target(format!("No monster named {}.", monster_id)).unwrap(),
+                        );
+                        return;
+                    }
+                };
+
+                let mut map = self.map.lock().unwrap();
+
+                // Numbered monsters share the spawners' count, so their names
+                // are unique. There can only be one of a monster with a fixed
+                // name.
+                if !template.has_number() && map.is_npc_name(&template.name) {
+                    context.enqueue_message_this(
+                        Error::other(format!("{} is already in the world.", template.name))
+                            .unwrap(),
+                    );
+                    return;
+                }
+
+                if !map.has_room(&room_number) {
+                    context.enqueue_message_this(
+                        Error::bad_room("Room does not exist.".to_string()).unwrap(),
+                    );
+                    return;
+                }
+
+                // New monsters are dirty, so the next update shows them to the room.
+                let monster = template.spawn(map.next_monster_number(), &mut self.rng);
+                let room = map.get_room_mut(&room_number).unwrap();
+                let reply = format!("Spawned {} in {}.", monster.name, room.get_name());
+                room.add_monster(monster);
+                reply
+            }
+            AdminCommand::Heal(name) => {
+                let name = name.unwrap_or_else(|| admin_name.clone());
+                let target_id = match self.get_player_id_by_name(&name) {
+                    Some(id) => id,
+                    None => return no_such_player(context, &name),
+                };
+
+                let mut players = self.players.lock().unwrap();
+                let entity = &mut players.get_mut(&target_id).unwrap().entity_info;
+                entity.health = entity.get_max_health();
+                entity.alive = true;
+                entity.update_dirty = true;
+                format!("Healed {}.", name)
+            }
+            AdminCommand::GiveGold(name, amount) => {
+                let target_id = match self.get_player_id_by_name(&name) {
+                    Some(id) => id,
+                    None => return no_such_player(context, &name),
+                };
+
+                let mut players = self.players.lock().unwrap();
+                let entity = &mut players.get_mut(&target_id).unwrap().entity_info;
+                entity.gold = entity.gold.saturating_add(amount);
+                entity.update_dirty = true;
+                format!("Gave {} gold to {}.", amount, name)
+            }
+            AdminCommand::Mute(name) => {
+                if self.muted.insert(name.clone()) {
+                    format!("Muted {}.", name)
+                } else {
+                    format!("{} is already muted.", name)
+                }
+            }
+            AdminCommand::Unmute(name) => {
+                if self.muted.remove(&name) {
+                    format!("Unmuted {}.", name)
+                } else {
+                    format!("{} is not muted.", name)
+                }
+            }
+            AdminCommand::RespawnRoom(room_number) => {
+                let mut map = self.map.lock().unwrap();
+                match map.run_spawner(&room_number, &mut self.rng) {
+                    Some(room) => format!("Respawned monsters in {}.", room.get_name()),
+                    None => {
+                        context.enqueue_message_this(
+                            Error::bad_room("Room does not exist.".to_string()).unwrap(),
+                        );
+                        return;
+                    }
+                }
+            }
+        };
+
+        context.enqueue_message_this(server_message(reply, admin_name));
+    }
+
+    // Takes the session's character out of the game for good.
+    fn remove_player(&mut self, client_id: &Uuid) {
+        self.chat_limiter.forget(client_id);
+        if let Some(player) = self.players.lock().unwrap().remove(client_id) {
+            self.release_player(&player);
+        }
+        self.map.lock().unwrap().clear_player(client_id);
+    }
+
+    fn release_player(&self, player: &Player) {
+        // Leaving while dead doesn't get around permadeath.
+        if self.config.death_policy == DeathPolicy::Permadeath
+            && player.started
+            && !player.entity_info.alive
+        {
+            if let Err(e) = self.character_store.remove(&player.entity_info.name) {
+                println!(
+                    "Failed to delete character {}: {}",
+                    player.entity_info.name, e
+                );
+            }
+        } else {
+            self.save_players(std::iter::once(player));
+        }
+    }
+
     pub fn map(&self) -> Arc<Mutex<Map>> {
         self.map.clone()
     }
@@ -290,50 +1403,99 @@ impl ExampleServer {
     pub fn players(&self) -> Arc<Mutex<HashMap<Uuid, Player>>> {
         self.players.clone()
     }
+
+    pub fn combat_log(&self) -> Arc<Mutex<CombatLog>> {
+        self.combat_log.clone()
+    }
+
+    pub fn packet_limiter(&self) -> Arc<Mutex<PacketLimiter>> {
+        self.packet_limiter.clone()
+    }
+
+    pub fn accounts(&self) -> Arc<AccountStore> {
+        self.accounts.clone()
+    }
 }
 
 impl ServerCallbacks for ExampleServer {
     fn on_connect(&mut self, context: &mut ServerEventContext) -> LurkServerError {
         println!("Connection made!");
 
-        context.enqueue_message_this(get_game_packet());
+        context.enqueue_message_this(get_game_packet(&self.config));
         self.players.lock().unwrap().insert(
             context.get_client_id(),
-            Player {
-                entity_info: Entity {
-                    update_dirty: false,
-                    name: String::new(),
-                    attack: 0,
-                    defense: 0,
-                    regen: 0,
-                    health: 0,
-                    gold: 0,
-                    location: 0,
-                    alive: false,
-                    monster: false,
-                    desc: String::new(),
-                    base_health: DEFAULT_HEALTH,
-                },
-                ready: false,
-                started: false,
-                id: context.get_client_id().clone(),
-            },
+            Player::new(context.get_client_id(), self.config.default_health),
         );
         Ok(())
     }
 
     fn on_disconnect(&mut self, client_id: &Uuid) {
         println!("Disconnect made.");
-        self.players.lock().unwrap().remove(client_id);
-        self.map.lock().unwrap().clear_player(&client_id);
+        self.packet_limiter.lock().unwrap().forget(client_id);
+
+        // A character in the world stays there for a while, in case the
+        // connection only dropped.
+        if self.config.reconnect_grace > 0 {
+            let mut players = self.players.lock().unwrap();
+            if let Some(player) = players.get_mut(client_id).filter(|player| player.started) {
+                println!("{} is linkdead.", player.entity_info.name);
+                self.chat_limiter.forget(client_id);
+                player.disconnected_at = Some(Instant::now());
+                player.entity_info.update_dirty = true;
+                self.save_players(std::iter::once(&*player));
+                return;
+            }
+        }
+
+        self.remove_player(client_id);
     }
 
-    fn on_message(
-        &mut self,
-        context: &mut ServerEventContext,
-        message: &Message,
-    ) -> LurkServerError {
-        println!("Received message packet.");
+    fn on_message(
+        &mut self,
+        context: &mut ServerEventContext,
+        message: &Message,
+    ) -> LurkServerError {
+        println!("Received message packet.");
+        if !self.check_rate(context, PacketKind::Message) {
+            return Ok(());
+        }
+        self.touch(&context.get_client_id());
+
+        if message.receiver == admin::SERVER_NAME {
+            return self.on_server_message(context, message);
+        }
+
+        let nearby_keeper = self
+            .map
+            .lock()
+            .unwrap()
+            .get_player_room(&context.get_client_id())
+            .and_then(|room| room.get_shop())
+            .map(|shop| shop.keeper.clone());
+
+        if nearby_keeper.as_ref() == Some(&message.receiver) {
+            return self.on_shop_message(context, message);
+        }
+
+        let sender_name = self
+            .players
+            .lock()
+            .unwrap()
+            .get(&context.get_client_id())
+            .map(|player| player.entity_info.name.clone());
+
+        if let Some(ref name) = sender_name {
+            if self.muted.contains(name) {
+                context.enqueue_message_this(
+                    Error::other("You have been muted.".to_string()).unwrap(),
+                );
+                return Ok(());
+            }
+        }
+
+        if let Some(channel) = Channel::from_receiver(&message.receiver) {
+            return self.on_channel_message(context, channel, message);
+        }
 
         if let Some(id) = self.get_player_id_by_name(&message.receiver) {
             context.enqueue_message(message.clone(), id.clone());
@@ -354,6 +1516,10 @@ impl ServerCallbacks for ExampleServer {
         change_room: &ChangeRoom,
     ) -> LurkServerError {
         println!("Change room packet received.");
+        if !self.check_rate(context, PacketKind::ChangeRoom) {
+            return Ok(());
+        }
+        self.touch(&context.get_client_id());
         let mut players = self.players.lock().unwrap();
         let mut map = self.map.lock().unwrap();
         if let Some(player) = players.get_mut(&context.get_client_id()) {
@@ -475,11 +1641,15 @@ impl ServerCallbacks for ExampleServer {
 
     fn on_fight(&mut self, context: &mut ServerEventContext, _: &Fight) -> LurkServerError {
         println!("Fight packet received.");
+        if !self.check_rate(context, PacketKind::Fight) {
+            return Ok(());
+        }
+        self.touch(&context.get_client_id());
 
         let mut fight_result_message: Option<String> = None;
 
         let mut players = self.players.lock().unwrap();
-        if let Some(player) = players.get_mut(&context.get_client_id()) {
+        if let Some(player) = players.get(&context.get_client_id()) {
             if !player.started {
                 context.enqueue_message_this(
                     Error::not_ready("You have not started.".to_string()).unwrap(),
@@ -493,31 +1663,64 @@ impl ServerCallbacks for ExampleServer {
                 );
                 return Ok(());
             }
+        } else {
+            println!("On Fight Error: Untracked player");
+            return Ok(());
+        }
 
-            if let Some(room) = self
-                .map
-                .lock()
-                .unwrap()
-                .get_player_room_mut(&context.get_client_id())
-            {
-                if let Some(monster) = room.get_random_monster_mut() {
-                    fight_result_message =
-                        Some(combat::handle_fight(&mut player.entity_info, monster));
-                } else {
-                    context.enqueue_message_this(
-                        Error::no_target("There are no enemies in this room.".to_string()).unwrap(),
-                    );
-                }
-            } else {
+        if let Some(room) = self
+            .map
+            .lock()
+            .unwrap()
+            .get_player_room_mut(&context.get_client_id())
+        {
+            // The player who started the fight always takes part, everyone
+            // else in the room joins if they've opted in.
+            let fighter_ids: Vec<Uuid> = room
+                .get_player_ids()
+                .into_iter()
+                .filter(|id| {
+                    players.get(id).map_or(false, |player| {
+                        *id == context.get_client_id()
+                            || (player.join_battle && player.started && player.entity_info.alive)
+                    })
+                })
+                .collect();
+
+            let mut monsters = room.get_alive_monsters_mut();
+            if monsters.is_empty() {
                 context.enqueue_message_this(
-                    Error::other(
-                        "Internal server error: Started player not placed in room.".to_string(),
-                    )
-                    .unwrap(),
+                    Error::no_target("There are no enemies in this room.".to_string()).unwrap(),
                 );
+            } else {
+                let events = {
+                    let mut fighters: Vec<(&Uuid, &mut Player)> = players
+                        .iter_mut()
+                        .filter(|&(id, _)| fighter_ids.contains(id))
+                        .collect();
+                    // Client ids change every connection, so the name keeps
+                    // the order the same when a seed is replayed.
+                    fighters.sort_by(|a, b| a.1.entity_info.name.cmp(&b.1.entity_info.name));
+                    let mut fighters: Vec<&mut Entity> = fighters
+                        .into_iter()
+                        .map(|(_, player)| &mut player.entity_info)
+                        .collect();
+
+                    combat::handle_group_fight(&mut fighters, &mut monsters, &mut self.rng)
+                };
+                fight_result_message = Some(combat::render_events(&events));
+                self.combat_log
+                    .lock()
+                    .unwrap()
+                    .record(room.get_number(), events);
             }
         } else {
-            println!("On Fight Error: Untracked player");
+            context.enqueue_message_this(
+                Error::other(
+                    "Internal server error: Started player not placed in room.".to_string(),
+                )
+                .unwrap(),
+            );
         }
 
         if let Some(message) = fight_result_message {
@@ -539,11 +1742,12 @@ impl ServerCallbacks for ExampleServer {
                     for monster in room.get_monster_packets(false) {
                         context.enqueue_message(monster, send_target.clone());
                     }
-                    context.enqueue_message(
-                        Message::new(message.clone(), "Server".to_string(), "You".to_string())
-                            .unwrap(),
-                        send_target.clone(),
-                    );
+                    if let Some(player) = players.get(&send_target) {
+                        context.enqueue_message(
+                            server_message(message.clone(), &player.entity_info.name),
+                            send_target.clone(),
+                        );
+                    }
                 }
             }
         }
@@ -551,19 +1755,152 @@ impl ServerCallbacks for ExampleServer {
         return Ok(());
     }
 
-    fn on_pvp_fight(&mut self, context: &mut ServerEventContext, _: &PvpFight) -> LurkServerError {
+    fn on_pvp_fight(
+        &mut self,
+        context: &mut ServerEventContext,
+        pvp_fight: &PvpFight,
+    ) -> LurkServerError {
         println!("Pvp fight packet.");
-        context.enqueue_message_this(
-            Error::no_pvp("Pvp is not currently on this server.".to_string()).unwrap(),
-        );
+        if !self.check_rate(context, PacketKind::Fight) {
+            return Ok(());
+        }
+        self.touch(&context.get_client_id());
+
+        if !self.config.pvp {
+            context.enqueue_message_this(
+                Error::no_pvp("Pvp is not currently on this server.".to_string()).unwrap(),
+            );
+            return Ok(());
+        }
+
+        let target_id = match self.get_player_id_by_name(&pvp_fight.target) {
+            Some(id) => id,
+            None => {
+                context.enqueue_message_this(
+                    Error::no_target("No such player exists.".to_string()).unwrap(),
+                );
+                return Ok(());
+            }
+        };
+
+        if target_id == context.get_client_id() {
+            context.enqueue_message_this(
+                Error::no_target("You cannot fight yourself.".to_string()).unwrap(),
+            );
+            return Ok(());
+        }
+
+        let mut players = self.players.lock().unwrap();
+        let map = self.map.lock().unwrap();
+
+        let fight_result_message = {
+            let player = match players.get(&context.get_client_id()) {
+                Some(player) => player,
+                None => {
+                    println!("On Pvp Fight Error: Untracked player");
+                    return Ok(());
+                }
+            };
+
+            if !player.started {
+                context.enqueue_message_this(
+                    Error::not_ready("You have not started.".to_string()).unwrap(),
+                );
+                return Ok(());
+            }
+
+            if !player.entity_info.alive {
+                context.enqueue_message_this(
+                    Error::other("The dead cannot fight.".to_string()).unwrap(),
+                );
+                return Ok(());
+            }
+
+            let player_room = match map.get_player_room(&context.get_client_id()) {
+                Some(room) => room,
+                None => {
+                    context.enqueue_message_this(
+                        Error::other(
+                            "Internal server error: Started player not placed in room.".to_string(),
+                        )
+                        .unwrap(),
+                    );
+                    return Ok(());
+                }
+            };
+
+            if !player_room.has_player(&target_id) {
+                context.enqueue_message_this(
+                    Error::no_target("That player is not in this room.".to_string()).unwrap(),
+                );
+                return Ok(());
+            }
+
+            if player_room.is_sanctuary() {
+                context.enqueue_message_this(
+                    Error::no_pvp("This room is a sanctuary.".to_string()).unwrap(),
+                );
+                return Ok(());
+            }
+
+            if !players[&target_id].entity_info.alive {
+                context.enqueue_message_this(
+                    Error::no_target("That player is already dead.".to_string()).unwrap(),
+                );
+                return Ok(());
+            }
+
+            // Take the target out so both players can be borrowed mutably.
+            let mut target = players.remove(&target_id).unwrap();
+            let events = combat::handle_fight(
+                &mut players
+                    .get_mut(&context.get_client_id())
+                    .unwrap()
+                    .entity_info,
+                &mut target.entity_info,
+                &mut self.rng,
+            );
+            players.insert(target_id, target);
+
+            let message = combat::render_events(&events);
+            self.combat_log
+                .lock()
+                .unwrap()
+                .record(player_room.get_number(), events);
+            message
+        };
+
+        if let Some(room) = map.get_player_room(&context.get_client_id()) {
+            for send_target in room.get_player_ids() {
+                for player_id in room.get_player_ids() {
+                    if let Some(player) = players.get(&player_id) {
+                        context.enqueue_message(player.get_character_packet(), send_target.clone());
+                    }
+                }
+                if let Some(player) = players.get(&send_target) {
+                    context.enqueue_message(
+                        server_message(fight_result_message.clone(), &player.entity_info.name),
+                        send_target.clone(),
+                    );
+                }
+            }
+        }
+
         Ok(())
     }
 
     fn on_loot(&mut self, context: &mut ServerEventContext, loot: &Loot) -> LurkServerError {
         println!("Loot packet received.");
+        if !self.check_rate(context, PacketKind::Loot) {
+            return Ok(());
+        }
+        self.touch(&context.get_client_id());
+
+        // Looked up before taking the players lock, which the lookup also takes.
+        let target_player_id = self.get_player_id_by_name(&loot.target);
 
         let mut players = self.players.lock().unwrap();
-        if let Some(player) = players.get_mut(&context.get_client_id()) {
+        if let Some(player) = players.get(&context.get_client_id()) {
             if !player.entity_info.alive {
                 context.enqueue_message_this(
                     Error::other("You cannot loot when you are dead.".to_string()).unwrap(),
@@ -577,43 +1914,91 @@ impl ServerCallbacks for ExampleServer {
                 );
                 return Ok(());
             }
+        } else {
+            return Ok(());
+        }
 
-            if let Some(room) = self
-                .map
-                .lock()
-                .unwrap()
-                .get_player_room_mut(&context.get_client_id())
-            {
-                match room.loot_monster(&loot.target) {
-                    LootMonsterResult::InvalidTarget => {
-                        context.enqueue_message_this(
-                            Error::no_target("Invalid target.".to_string()).unwrap(),
-                        );
-                    }
-                    LootMonsterResult::MonsterAlive => {
-                        context.enqueue_message_this(
-                            Error::no_target("Can't loot living target.".to_string()).unwrap(),
-                        );
-                    }
-                    LootMonsterResult::Success(mut monster) => {
-                        player.entity_info.gold += monster.gold;
-                        monster.gold = 0;
-                        player.entity_info.update_dirty = true;
-
-                        for player_id in room.get_player_ids() {
-                            println!("Notifying player {:?} of monster removal.", &player_id);
-                            context.enqueue_message(monster.clone(), player_id.clone());
-                        }
-                    }
+        let mut map = self.map.lock().unwrap();
+        let room = match map.get_player_room_mut(&context.get_client_id()) {
+            Some(room) => room,
+            None => return Ok(()),
+        };
+
+        match room.loot_monster(&loot.target) {
+            LootMonsterResult::InvalidTarget => {}
+            LootMonsterResult::MonsterAlive => {
+                context.enqueue_message_this(
+                    Error::no_target("Can't loot living target.".to_string()).unwrap(),
+                );
+                return Ok(());
+            }
+            LootMonsterResult::Success(mut monster, items) => {
+                let player = players.get_mut(&context.get_client_id()).unwrap();
+                player.entity_info.gold = player.entity_info.gold.saturating_add(monster.gold);
+                monster.gold = 0;
+                player.entity_info.update_dirty = true;
+
+                if !items.is_empty() {
+                    let found: Vec<String> = items.iter().map(|item| item.summary()).collect();
+                    context.enqueue_message_this(server_message(
+                        format!("You found: {}.", found.join(", ")),
+                        &player.entity_info.name,
+                    ));
+                    player.entity_info.inventory.items.extend(items);
+                }
+
+                for player_id in room.get_player_ids() {
+                    println!("Notifying player {:?} of monster removal.", &player_id);
+                    context.enqueue_message(monster.clone(), player_id.clone());
                 }
+                return Ok(());
             }
         }
 
+        // Not a monster, so it may be the body of another player in the room.
+        let target_id = match target_player_id {
+            Some(id) if id != context.get_client_id() && room.has_player(&id) => id,
+            _ => {
+                context
+                    .enqueue_message_this(Error::no_target("Invalid target.".to_string()).unwrap());
+                return Ok(());
+            }
+        };
+
+        let (gold, target_name) = {
+            let target = &mut players.get_mut(&target_id).unwrap().entity_info;
+            if target.alive {
+                context.enqueue_message_this(
+                    Error::no_target("Can't loot living target.".to_string()).unwrap(),
+                );
+                return Ok(());
+            }
+
+            let gold = target.gold;
+            target.gold = 0;
+            target.update_dirty = true;
+            (gold, target.name.clone())
+        };
+
+        let player = players.get_mut(&context.get_client_id()).unwrap();
+        player.entity_info.gold = player.entity_info.gold.saturating_add(gold);
+        player.entity_info.update_dirty = true;
+
+        context.enqueue_message_this(server_message(
+            format!("You took {} gold from {}.", gold, target_name),
+            &player.entity_info.name,
+        ));
+        context.enqueue_message_this(player.get_character_packet());
+
         Ok(())
     }
 
     fn on_start(&mut self, context: &mut ServerEventContext, _: &Start) -> LurkServerError {
         println!("Start packet received.");
+        if !self.check_rate(context, PacketKind::Start) {
+            return Ok(());
+        }
+        self.touch(&context.get_client_id());
         let mut players = self.players.lock().unwrap();
         if let Some(player) = players.get_mut(&context.get_client_id()) {
             if player.started {
@@ -626,13 +2011,16 @@ impl ServerCallbacks for ExampleServer {
 
             if player.ready {
                 player.started = true;
-                player.entity_info.location =
-                    self.map.lock().unwrap().get_start_room().get_number();
-                self.map
-                    .lock()
-                    .unwrap()
-                    .get_start_room_mut()
-                    .place_player(&player.id);
+                {
+                    // Restored characters pick up where they left off.
+                    let mut map = self.map.lock().unwrap();
+                    if !map.has_room(&player.entity_info.location) {
+                        player.entity_info.location = map.get_start_room().get_number();
+                    }
+                    map.get_room_mut(&player.entity_info.location)
+                        .expect("Bug: Player location not in map.")
+                        .place_player(&player.id);
+                }
 
                 context.enqueue_message_this(player.get_character_packet());
                 println!("Enqueued character packet.");
@@ -710,17 +2098,123 @@ impl ServerCallbacks for ExampleServer {
         character: &Character,
     ) -> LurkServerError {
         println!("Got character message.");
+        if !self.check_rate(context, PacketKind::Character) {
+            return Ok(());
+        }
+        self.touch(&context.get_client_id());
+
+        // Once started the name can't change, so it only needs checking before.
+        // A ready player resending their character is already logged in.
+        let (started, logged_in) = self
+            .players
+            .lock()
+            .unwrap()
+            .get(&context.get_client_id())
+            .map_or((false, false), |player| {
+                (
+                    player.started,
+                    player.ready && player.entity_info.name == character.player_name,
+                )
+            });
+
+        // A free name is only claimed once the character is accepted, so a
+        // rejected character doesn't lock anyone out of it.
+        let mut claim = false;
+        if !started {
+            let checked = self
+                .check_name(&context.get_client_id(), &character.player_name)
+                .and_then(|_| {
+                    if logged_in {
+                        Ok(false)
+                    } else {
+                        self.authenticate(&context.get_client_id(), &character.player_name)
+                    }
+                });
+
+            claim = match checked {
+                Ok(claim) => claim,
+                Err(error) => {
+                    context.enqueue_message_this(error);
+                    return Ok(());
+                }
+            };
+
+            if self.reconnect(context, &character.player_name) {
+                if claim {
+                    let mut players = self.players.lock().unwrap();
+                    if let Some(player) = players.get_mut(&context.get_client_id()) {
+                        if let Err(error) = self.claim_name(player, &character.player_name) {
+                            context.enqueue_message_this(error);
+                        }
+                    }
+                }
+                return Ok(());
+            }
+        }
+
+        if started {
+            return self.update_started_character(context, character);
+        }
+
+        let saved = match self.character_store.load(&character.player_name) {
+            Some(ref saved)
+                if !saved.alive && self.config.death_policy == DeathPolicy::Permadeath =>
+            {
+                if let Err(e) = self.character_store.remove(&saved.name) {
+                    println!("Failed to delete character {}: {}", saved.name, e);
+                }
+                None
+            }
+            saved => saved,
+        };
+
+        if let Some(saved) = saved {
+            let mut players = self.players.lock().unwrap();
+            if let Some(player) = players.get_mut(&context.get_client_id()) {
+                if !player.started {
+                    if claim {
+                        if let Err(error) = self.claim_name(player, &character.player_name) {
+                            context.enqueue_message_this(error);
+                            return Ok(());
+                        }
+                    }
+
+                    println!("Restoring saved character {}.", saved.name);
+                    context.enqueue_message_this(Accept::new(CHARACTER_TYPE));
+
+                    player.ready = true;
+                    player.join_battle = character.join_battle;
+                    player.entity_info = saved.to_entity();
+
+                    // The dead come back at the start of the dungeon.
+                    if !player.entity_info.alive {
+                        player.entity_info.alive = true;
+                        player.entity_info.health = player.entity_info.get_max_health();
+                        player.entity_info.location =
+                            self.map.lock().unwrap().get_start_room().get_number();
+                    }
+
+                    context.enqueue_message_this(player.get_character_packet());
+                    return Ok(());
+                }
+            }
+        }
+
+        let initial_points = self.config.initial_points;
+        let stat_limit = self.config.stat_limit;
 
-        if character.attack + character.defense + character.regeneration > INITIAL_POINTS {
+        if character.attack as u32 + character.defense as u32 + character.regeneration as u32
+            > initial_points as u32
+        {
             context.enqueue_message_this(
                 Error::stat_error("Invalid amount of stat points spent.".to_string()).unwrap(),
             );
             return Ok(());
         }
 
-        if character.attack > STAT_LIMIT
-            || character.defense > STAT_LIMIT
-            || character.regeneration > STAT_LIMIT
+        if character.attack > stat_limit
+            || character.defense > stat_limit
+            || character.regeneration > stat_limit
         {
             context.enqueue_message_this(
                 Error::stat_error("One or more attributes were set too high.".to_string()).unwrap(),
@@ -731,22 +2225,31 @@ impl ServerCallbacks for ExampleServer {
         let mut players = self.players.lock().unwrap();
         if let Some(player) = players.get_mut(&context.get_client_id()) {
             if !player.started {
+                if claim {
+                    if let Err(error) = self.claim_name(player, &character.player_name) {
+                        context.enqueue_message_this(error);
+                        return Ok(());
+                    }
+                }
+
                 println!("Accept character!");
                 context.enqueue_message_this(Accept::new(CHARACTER_TYPE));
                 println!("Accept enqueued!");
 
                 player.ready = true;
+                player.join_battle = character.join_battle;
 
                 let attack_boost =
-                    (1.25f32 * (character.attack as f32 / INITIAL_POINTS as f32)).max(1f32);
+                    (1.25f32 * (character.attack as f32 / initial_points as f32)).max(1f32);
                 let defense_boost =
-                    (1.25f32 * (character.defense as f32 / INITIAL_POINTS as f32)).max(1f32);
+                    (1.25f32 * (character.defense as f32 / initial_points as f32)).max(1f32);
                 let regen_boost =
-                    (1.25f32 * (character.regeneration as f32 / INITIAL_POINTS as f32)).max(1f32);
+                    (1.25f32 * (character.regeneration as f32 / initial_points as f32)).max(1f32);
 
                 let attack = (character.attack as f32 * attack_boost).floor() as u16;
                 let defense = (character.defense as f32 * defense_boost).floor() as u16;
                 let regen = (character.regeneration as f32 * regen_boost).floor() as u16;
+                let start_room = self.map.lock().unwrap().get_start_room().get_number();
 
                 player.entity_info = Entity {
                     update_dirty: false,
@@ -754,20 +2257,24 @@ impl ServerCallbacks for ExampleServer {
                     attack,
                     defense,
                     regen,
-                    health: DEFAULT_HEALTH,
-                    gold: DEFAULT_GOLD,
-                    location: 0,
+                    health: self.config.default_health,
+                    gold: self.config.default_gold,
+                    location: start_room,
                     alive: true,
                     monster: false,
                     desc: character.description.clone(),
-                    base_health: DEFAULT_HEALTH,
+                    base_health: self.config.default_health,
+                    experience: 0,
+                    level: 1,
+                    stat_points: 0,
+                    inventory: Inventory::default(),
+                    roam_chance: 0.0,
+                    aggression: Aggression::Never,
+                    hostile: false,
+                    home: None,
                 };
 
                 context.enqueue_message_this(player.get_character_packet());
-            } else {
-                context.enqueue_message_this(
-                    Error::other("Your stats cannot be edited at this time.".to_string()).unwrap(),
-                );
             }
         } else {
             context.enqueue_message_this(
@@ -781,19 +2288,24 @@ impl ServerCallbacks for ExampleServer {
         Ok(())
     }
 
+    // The limiter keeps its hold on the connection until it closes, so a
+    // dropped client can't leave and come straight back in.
     fn on_leave(&mut self, client_id: &Uuid) -> LurkServerError {
         println!("Leave packet received.");
-        self.on_disconnect(client_id);
+        self.remove_player(client_id);
         Ok(())
     }
 
     fn update(&mut self, context: &UpdateContext) {
         let current = Instant::now();
-        if current.duration_since(self.last_update_time) > Duration::from_secs(1) {
+        if current.duration_since(self.last_update_time) > self.config.tick_interval() {
             println!("Update: {:?}", current);
             self.last_update_time = current;
 
-            let mut players = self.players.lock().unwrap();
+            // Locked through a clone of the handle so the update helpers can
+            // still borrow the server mutably.
+            let players = self.players.clone();
+            let mut players = players.lock().unwrap();
 
             for (_, player) in players.iter_mut() {
                 player.entity_info.regen();
@@ -801,7 +2313,51 @@ impl ServerCallbacks for ExampleServer {
 
             self.map.lock().unwrap().update_monsters();
 
-            for (target_id, _) in players.iter() {
+            // Both rooms see the monster's new location, so it leaves one and
+            // arrives in the other.
+            {
+                let mut map = self.map.lock().unwrap();
+                for (from, to, packet) in map.update_roaming(&mut self.rng) {
+                    for room_number in [from, to].iter() {
+                        for player_id in map.get_room(room_number).unwrap().get_player_ids() {
+                            context.enqueue_message(packet.clone(), player_id);
+                        }
+                    }
+                }
+            }
+            self.map
+                .lock()
+                .unwrap()
+                .update_respawns(current, &mut self.rng);
+
+            if current.duration_since(self.last_monster_attack)
+                > self.config.monster_attack_interval()
+            {
+                self.last_monster_attack = current;
+                self.update_aggression(context, &mut players);
+            }
+
+            self.update_deaths(context, &mut players, current);
+            self.update_linkdead(&mut players, current);
+            self.update_idle(context, &mut players, current);
+
+            for player in players.values_mut() {
+                let entity = &mut player.entity_info;
+                if progression::level_up(entity, self.config.points_per_level) > 0 {
+                    context.enqueue_message(
+                        server_message(
+                            format!(
+                                "You reached level {}! You have {} stat points to spend.",
+                                entity.level, entity.stat_points
+                            ),
+                            &entity.name,
+                        ),
+                        player.id,
+                    );
+                }
+            }
+
+            for (target_id, _) in players.iter().filter(|&(_, player)| !player.is_linkdead()) {
                 if let Some(player_room) = self.map.lock().unwrap().get_player_room(&target_id) {
                     for player_id in player_room.get_player_ids() {
                         if let Some(player) = players.get(&player_id) {
@@ -825,33 +2381,110 @@ impl ServerCallbacks for ExampleServer {
             for (_, player) in players.iter_mut() {
                 player.entity_info.update_dirty = false;
             }
+
+            if current.duration_since(self.last_save_time) > AUTOSAVE_INTERVAL {
+                self.last_save_time = current;
+                self.save_players(players.values());
+            }
         }
     }
 }
 
 fn main() {
-    let args: Vec<String> = std::env::args().collect();
-    let addr : SocketAddr = args
-        .get(1)
-        .expect("Insufficient arguments")
-        .parse()
-        .expect("Invalid socket address.");
+    let config = match config::load_config(std::env::args().collect()) {
+        Ok(config) => config,
+        Err(e) => {
+            println!("{}", e);
+            std::process::exit(1);
+        }
+    };
+
+    let seed = config.seed.unwrap_or_else(rng::random_seed);
+    println!("Using seed {}.", seed);
+    let mut rng = rng::seeded_rng(seed);
+
+    let items = match items::load_items(&config.items) {
+        Ok(items) => items,
+        Err(e) => {
+            println!("{}", e);
+            std::process::exit(1);
+        }
+    };
+
+    let catalogue = match monster_catalogue::load_catalogue(&config.monsters, &items) {
+        Ok(catalogue) => catalogue,
+        Err(e) => {
+            println!("{}", e);
+            std::process::exit(1);
+        }
+    };
 
+    let shops = match shop::load_shops(&config.shops, &items) {
+        Ok(shops) => shops,
+        Err(e) => {
+            println!("{}", e);
+            std::process::exit(1);
+        }
+    };
+
+    let map = match map_file::load_map(&config.map, &catalogue, &shops, &mut rng) {
+        Ok(map) => map,
+        Err(e) => {
+            println!("{}", e);
+            std::process::exit(1);
+        }
+    };
+
+    let character_store = match CharacterStore::open(&config.store) {
+        Ok(store) => store,
+        Err(e) => {
+            println!("Failed to open character store '{}': {}", config.store, e);
+            std::process::exit(1);
+        }
+    };
 
-    let behaviour = ExampleServer::new();
+    let accounts = match AccountStore::open(
+        &config.accounts,
+        config.max_login_failures,
+        config.login_lockout(),
+    ) {
+        Ok(accounts) => accounts,
+        Err(e) => {
+            println!("Failed to open account store '{}': {}", config.accounts, e);
+            std::process::exit(1);
+        }
+    };
 
-    let rest_server = RestService::new(behaviour.map(), behaviour.players())
+    // Validated to be set when the config was loaded.
+    let addr = config.address.unwrap();
+    let rest_addr = config.rest_address;
+    let rest_admin_token = config.rest_admin_token.clone();
+    let client_timeout = config.client_timeout();
+
+    let behaviour = ExampleServer::new(map, catalogue, character_store, accounts, config, rng);
+
+    if let Some(rest_addr) = rest_addr {
+        let rest_server = RestService::new(
+            behaviour.map(),
+            behaviour.players(),
+            behaviour.combat_log(),
+            behaviour.packet_limiter(),
+            behaviour.accounts(),
+            rest_admin_token,
+        )
         .expect("Failed to create REST service.");
 
-    let mut server = Server::create(
-        addr,
-        Duration::from_secs(20),
-        Box::new(behaviour),
-    )
-    .expect("Unable to create server.");
-    /*thread::spawn(move || {
-        rest_server.start(port_number + 1);
-    });*/
+        match rest_server.start(rest_addr) {
+            Ok(local_addr) => println!("REST service listening on http://{}", local_addr),
+            Err(e) => {
+                println!("Failed to start REST service on {}: {}", rest_addr, e);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    let mut server = Server::create(addr, client_timeout, Box::new(behaviour))
+        .expect("Unable to create server.");
     match server.start() {
         Ok(_) => println!("Success"),
         Err(_) => println!("Failed to start server"),
@@ -1,15 +1,20 @@
 use entity::Entity;
+use items::Item;
 use liblurk::protocol::protocol_message::Character;
-use monster_spawn::MonsterSpawn;
-use rand::*;
+use monster_spawn::{MonsterNumbers, MonsterSpawn};
+use rand::Rng;
+use rng::GameRng;
+use shop::Shop;
 use std::collections::HashMap;
 use std::collections::HashSet;
+use std::time::{Duration, Instant};
 use uuid::Uuid;
 
 pub enum LootMonsterResult {
     InvalidTarget,
     MonsterAlive,
-    Success(Character),
+    // The removed monster's packet, and whatever it was carrying.
+    Success(Character, Vec<Item>),
 }
 
 pub enum MovePlayerResult {
@@ -18,9 +23,17 @@ pub enum MovePlayerResult {
     Success,
 }
 
+#[derive(Clone)]
+pub struct RespawnPolicy {
+    pub interval: Duration,
+    pub max_population: usize,
+    pub only_when_empty: bool,
+}
+
 pub struct Map {
     rooms: HashMap<u16, Room>,
     start_room_id: u16,
+    monster_numbers: MonsterNumbers,
 }
 
 impl Map {
@@ -42,6 +55,18 @@ impl Map {
         self.rooms.get_mut(room_number)
     }
 
+    // For monsters spawned outside of a room's spawner, so their "{n}" doesn't
+    // clash with any spawned monster's.
+    pub fn next_monster_number(&mut self) -> u32 {
+        self.monster_numbers.next()
+    }
+
+    pub fn run_spawner(&mut self, room_number: &u16, rng: &mut GameRng) -> Option<&Room> {
+        let room = self.rooms.get_mut(room_number)?;
+        room.run_spawner(rng, &mut self.monster_numbers);
+        Some(room)
+    }
+
     pub fn move_player(&mut self, player_id: &Uuid, new_location: u16) -> MovePlayerResult {
         if !self.has_player(&player_id) {
             return MovePlayerResult::InvalidPlayer;
@@ -89,6 +114,26 @@ impl Map {
         false
     }
 
+    pub fn get_room_numbers(&self) -> Vec<u16> {
+        let mut result: Vec<u16> = self.rooms.keys().cloned().collect();
+        result.sort();
+        result
+    }
+
+    // Whether a monster or shopkeeper anywhere in the world goes by the name,
+    // ignoring case.
+    pub fn is_npc_name(&self, name: &str) -> bool {
+        self.rooms.values().any(|room| {
+            room.monsters
+                .iter()
+                .any(|monster| monster.name.eq_ignore_ascii_case(name))
+                || room
+                    .shop
+                    .as_ref()
+                    .map_or(false, |shop| shop.keeper.eq_ignore_ascii_case(name))
+        })
+    }
+
     pub fn has_room(&self, room_id: &u16) -> bool {
         self.rooms.contains_key(&room_id)
     }
@@ -99,12 +144,84 @@ impl Map {
         }
     }
 
+    // Hands a player's place in the world over to a new session.
+    pub fn rebind_player(&mut self, old_id: &Uuid, new_id: &Uuid) {
+        if let Some(room) = self.get_player_room_mut(old_id) {
+            room.remove_player(old_id);
+            room.place_player(new_id);
+        }
+    }
+
     pub fn update_monsters(&mut self) {
         for (_, room) in self.rooms.iter_mut() {
             room.update_monsters();
         }
     }
 
+    // Rooms are walked in order so a seeded RNG gives the same spawns each run.
+    pub fn update_respawns(&mut self, now: Instant, rng: &mut GameRng) {
+        for room_number in self.get_room_numbers() {
+            let alive_count = self
+                .rooms
+                .values()
+                .flat_map(|room| room.monsters.iter())
+                .filter(|monster| monster.alive && monster.home == Some(room_number))
+                .count();
+
+            self.rooms.get_mut(&room_number).unwrap().update_respawn(
+                now,
+                rng,
+                &mut self.monster_numbers,
+                alive_count,
+            );
+        }
+    }
+
+    // Moves living monsters to a random adjacent room, each with its own roam
+    // chance. Monsters never wander into a sanctuary. Returns the room each
+    // one left, the room it entered and its new packet.
+    pub fn update_roaming(&mut self, rng: &mut GameRng) -> Vec<(u16, u16, Character)> {
+        let mut wanderers = vec![];
+
+        for room_number in self.get_room_numbers() {
+            let destinations: Vec<u16> = self.rooms[&room_number]
+                .adjacent_rooms
+                .iter()
+                .filter(|number| !self.rooms[number].sanctuary)
+                .cloned()
+                .collect();
+
+            if destinations.is_empty() {
+                continue;
+            }
+
+            let room = self.rooms.get_mut(&room_number).unwrap();
+            let mut i = 0;
+            while i < room.monsters.len() {
+                let monster = &room.monsters[i];
+                if monster.alive
+                    && monster.roam_chance > 0.0
+                    && rng.gen::<f32>() < monster.roam_chance
+                {
+                    let destination = destinations[rng.gen_range(0, destinations.len())];
+                    wanderers.push((room_number, destination, room.monsters.remove(i)));
+                } else {
+                    i += 1;
+                }
+            }
+        }
+
+        // Monsters are only placed once every room has been walked, so none
+        // moves twice in one update.
+        let mut moves = vec![];
+        for (from, to, monster) in wanderers {
+            let room = self.rooms.get_mut(&to).unwrap();
+            room.add_monster(monster);
+            moves.push((from, to, monster_packet(room.monsters.last().unwrap())));
+        }
+        moves
+    }
+
     pub fn clear_update_flags(&mut self) {
         for (_, room) in self.rooms.iter_mut() {
             room.clear_update_flag();
@@ -120,6 +237,10 @@ pub struct Room {
     player_ids: HashSet<Uuid>,
     spawner: Box<MonsterSpawn + Send>,
     monsters: Vec<Entity>,
+    respawn_policy: Option<RespawnPolicy>,
+    last_respawn: Instant,
+    sanctuary: bool,
+    shop: Option<Shop>,
 }
 
 impl Room {
@@ -127,6 +248,19 @@ impl Room {
         self.num
     }
 
+    // Players can't fight each other in a sanctuary.
+    pub fn is_sanctuary(&self) -> bool {
+        self.sanctuary
+    }
+
+    pub fn get_shop(&self) -> Option<&Shop> {
+        self.shop.as_ref()
+    }
+
+    pub fn get_shop_mut(&mut self) -> Option<&mut Shop> {
+        self.shop.as_mut()
+    }
+
     pub fn place_player(&mut self, player_id: &Uuid) {
         if !self.player_ids.contains(&player_id) {
             self.player_ids.insert(player_id.clone());
@@ -159,57 +293,100 @@ impl Room {
         &self.adjacent_rooms
     }
 
-    pub fn run_spawner(&mut self) {
-        self.monsters.extend(self.spawner.spawn_monsters());
+    pub fn add_monster(&mut self, mut monster: Entity) {
+        monster.location = self.num;
+        self.monsters.push(monster);
     }
 
-    pub fn get_monster_packets(&self, force: bool) -> Vec<Character> {
-        let mut result: Vec<Character> = vec![];
-        for monster in self.monsters.iter() {
-            if monster.update_dirty || force {
-                result.push(
-                    Character::new(
-                        monster.name.clone(),
-                        monster.health > 0,
-                        true,
-                        true,
-                        true,
-                        true,
-                        monster.attack,
-                        monster.defense,
-                        monster.regen,
-                        monster.health,
-                        monster.gold,
-                        monster.location,
-                        monster.desc.clone(),
-                    )
-                    .expect("Failed on monster to character packet."),
-                );
-            }
+    fn run_spawner(&mut self, rng: &mut GameRng, numbers: &mut MonsterNumbers) {
+        for mut monster in self.spawner.spawn_monsters(rng, numbers) {
+            monster.home = Some(self.num);
+            self.add_monster(monster);
         }
-        result
+        self.last_respawn = Instant::now();
     }
 
-    pub fn get_random_monster_mut(&mut self) -> Option<&mut Entity> {
-        if self.monsters.is_empty() || self.all_monsters_dead() {
-            return None;
+    // Tops the room back up to its policy's population once the interval has
+    // passed. New monsters are spawned dirty so they get pushed to players.
+    // alive_count is how many of the room's own monsters are still alive,
+    // including any that have wandered off.
+    fn update_respawn(
+        &mut self,
+        now: Instant,
+        rng: &mut GameRng,
+        numbers: &mut MonsterNumbers,
+        alive_count: usize,
+    ) {
+        let policy = match self.respawn_policy {
+            Some(ref policy) => policy.clone(),
+            None => return,
+        };
+
+        if now.duration_since(self.last_respawn) < policy.interval {
+            return;
         }
 
-        if self.monsters.len() == 1 {
-            return Some(self.monsters.get_mut(0).unwrap());
+        if policy.only_when_empty && !self.player_ids.is_empty() {
+            return;
         }
 
-        let alive_monster_indices = self.get_alive_monsters_indices();
+        self.last_respawn = now;
+
+        if alive_count >= policy.max_population {
+            return;
+        }
+
+        let mut spawned = self.spawner.spawn_monsters(rng, numbers);
+        spawned.truncate(policy.max_population - alive_count);
+        println!("Respawned {} monsters in {}.", spawned.len(), self.name);
+        for mut monster in spawned {
+            monster.home = Some(self.num);
+            self.add_monster(monster);
+        }
+    }
+
+    pub fn get_monster_packets(&self, force: bool) -> Vec<Character> {
+        let mut result: Vec<Character> = vec![];
+        for monster in self.monsters.iter() {
+            if monster.update_dirty || force {
+                result.push(monster_packet(monster));
+            }
+        }
 
-        if alive_monster_indices.len() == 1 {
-            let idx = alive_monster_indices[0];
-            return self.monsters.get_mut(idx);
+        // The shopkeeper never changes, so is only sent on entering the room.
+        // It isn't one of the room's monsters and can't be fought or looted.
+        if let Some(shop) = self.shop.as_ref().filter(|_| force) {
+            result.push(
+                Character::new(
+                    shop.keeper.clone(),
+                    true,
+                    false,
+                    true,
+                    true,
+                    true,
+                    0,
+                    0,
+                    0,
+                    0,
+                    0,
+                    self.num,
+                    shop.description.clone(),
+                )
+                .expect("Failed on shopkeeper to character packet."),
+            );
         }
+        result
+    }
 
-        let idx = thread_rng().gen_range(0, alive_monster_indices.len() - 1);
+    pub fn get_monsters(&self) -> &Vec<Entity> {
+        &self.monsters
+    }
 
-        let monster_idx = alive_monster_indices[idx];
-        self.monsters.get_mut(monster_idx)
+    pub fn get_alive_monsters_mut(&mut self) -> Vec<&mut Entity> {
+        self.monsters
+            .iter_mut()
+            .filter(|monster| monster.alive)
+            .collect()
     }
 
     pub fn loot_monster(&mut self, target: &String) -> LootMonsterResult {
@@ -220,6 +397,7 @@ impl Room {
                 LootMonsterResult::MonsterAlive
             } else {
                 let monster = self.monsters.remove(monster_index);
+                let items = monster.inventory.items.clone();
                 LootMonsterResult::Success(
                     Character::new(
                         monster.name.clone(),
@@ -237,6 +415,7 @@ impl Room {
                         monster.desc.clone(),
                     )
                     .expect("Failed to create monster packet."),
+                    items,
                 )
             }
         } else {
@@ -245,33 +424,21 @@ impl Room {
     }
 
     fn get_monster_index(&self, target: &String) -> Option<usize> {
+        // A respawned monster can share its name with an unlooted corpse, so
+        // corpses are matched first.
         for i in 0..self.monsters.len() {
-            if self.monsters.get(i).unwrap().name == *target {
+            let monster = self.monsters.get(i).unwrap();
+            if !monster.alive && monster.name == *target {
                 return Some(i);
             }
         }
-        None
-    }
 
-    fn get_alive_monsters_indices(&self) -> Vec<usize> {
-        let mut result = vec![];
         for i in 0..self.monsters.len() {
-            if self.monsters.get(i).unwrap().alive {
-                result.push(i);
-            }
-        }
-        result
-    }
-
-    fn all_monsters_dead(&self) -> bool {
-        let mut result = true;
-        for monster in self.monsters.iter() {
-            if monster.alive {
-                result = false;
-                break;
+            if self.monsters.get(i).unwrap().name == *target {
+                return Some(i);
             }
         }
-        result
+        None
     }
 
     pub fn get_player_ids(&self) -> Vec<Uuid> {
@@ -295,6 +462,25 @@ impl Room {
     }
 }
 
+fn monster_packet(monster: &Entity) -> Character {
+    Character::new(
+        monster.name.clone(),
+        monster.health > 0,
+        true,
+        true,
+        true,
+        true,
+        monster.attack,
+        monster.defense,
+        monster.regen,
+        monster.health,
+        monster.gold,
+        monster.location,
+        monster.desc.clone(),
+    )
+    .expect("Failed on monster to character packet.")
+}
+
 pub struct MapBuilder {
     buildee: Map,
     room_number: u16,
@@ -306,6 +492,7 @@ impl MapBuilder {
             buildee: Map {
                 rooms: HashMap::new(),
                 start_room_id: 0,
+                monster_numbers: MonsterNumbers::default(),
             },
             room_number: 1,
         }
@@ -325,6 +512,10 @@ impl MapBuilder {
             player_ids: HashSet::new(),
             spawner: monster_spawner,
             monsters: vec![],
+            respawn_policy: None,
+            last_respawn: Instant::now(),
+            sanctuary: false,
+            shop: None,
         };
 
         self.buildee.rooms.insert(self.room_number, room);
@@ -353,6 +544,30 @@ impl MapBuilder {
         Ok(())
     }
 
+    pub fn set_respawn_policy(&mut self, room_num: u16, policy: RespawnPolicy) -> Result<(), ()> {
+        if let Some(room) = self.buildee.get_room_mut(&room_num) {
+            room.respawn_policy = Some(policy);
+            return Ok(());
+        }
+        Err(())
+    }
+
+    pub fn set_sanctuary(&mut self, room_num: u16, sanctuary: bool) -> Result<(), ()> {
+        if let Some(room) = self.buildee.get_room_mut(&room_num) {
+            room.sanctuary = sanctuary;
+            return Ok(());
+        }
+        Err(())
+    }
+
+    pub fn set_shop(&mut self, room_num: u16, shop: Shop) -> Result<(), ()> {
+        if let Some(room) = self.buildee.get_room_mut(&room_num) {
+            room.shop = Some(shop);
+            return Ok(());
+        }
+        Err(())
+    }
+
     pub fn set_start_room(&mut self, room_num: u16) -> Result<(), ()> {
         if !self.buildee.rooms.contains_key(&room_num) {
             return Err(());
@@ -363,10 +578,10 @@ impl MapBuilder {
         Ok(())
     }
 
-    pub fn complete(mut self) -> Result<Map, ()> {
+    pub fn complete(mut self, rng: &mut GameRng) -> Result<Map, ()> {
         if self.buildee.start_room_id != 0 {
-            for (_, room) in self.buildee.rooms.iter_mut() {
-                room.run_spawner();
+            for room_number in self.buildee.get_room_numbers() {
+                self.buildee.run_spawner(&room_number, rng);
             }
 
             return Ok(self.buildee);
@@ -374,3 +589,135 @@ impl MapBuilder {
         Err(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use entity::Aggression;
+    use monster_spawn::monster_spawners;
+    use monster_spawn::{MonsterSpawner, MonsterTemplate};
+    use rng::seeded_rng;
+
+    fn rats(roam_chance: f32, population: u8) -> MonsterSpawner {
+        let rat = MonsterTemplate {
+            name: "Rat {n}".to_string(),
+            attack: 10,
+            defense: 10,
+            regen: 1,
+            health: 20,
+            gold: (0, 5),
+            description: "A rat.".to_string(),
+            roam_chance,
+            aggression: Aggression::Never,
+            drops: vec![],
+        };
+
+        monster_spawners::template_spawner(vec![(rat, 1)], (population, population))
+    }
+
+    // A hall (a sanctuary), a cellar full of rats and an attic, in a line.
+    fn house(rats: MonsterSpawner, respawn: Option<RespawnPolicy>) -> Map {
+        let mut builder = MapBuilder::new();
+        let hall = builder.register_room(
+            "Hall",
+            "A hall.",
+            monster_spawners::composite_spawner(vec![]),
+        );
+        let cellar = builder.register_room("Cellar", "A cellar.", rats);
+        let attic = builder.register_room(
+            "Attic",
+            "An attic.",
+            monster_spawners::composite_spawner(vec![]),
+        );
+
+        builder.link_rooms(hall, cellar).unwrap();
+        builder.link_rooms(cellar, attic).unwrap();
+        builder.set_sanctuary(hall, true).unwrap();
+        builder.set_start_room(hall).unwrap();
+        if let Some(policy) = respawn {
+            builder.set_respawn_policy(cellar, policy).unwrap();
+        }
+
+        builder.complete(&mut seeded_rng(1)).unwrap()
+    }
+
+    fn population(map: &Map, room_number: u16) -> usize {
+        map.get_room(&room_number).unwrap().get_monsters().len()
+    }
+
+    #[test]
+    fn monsters_that_roamed_off_still_count_towards_their_home() {
+        let mut map = house(
+            rats(0.0, 2),
+            Some(RespawnPolicy {
+                interval: Duration::from_secs(10),
+                max_population: 2,
+                only_when_empty: false,
+            }),
+        );
+        let mut rng = seeded_rng(1);
+
+        let rat = map.get_room_mut(&2).unwrap().monsters.remove(0);
+        map.get_room_mut(&3).unwrap().add_monster(rat);
+
+        map.update_respawns(Instant::now() + Duration::from_secs(60), &mut rng);
+        assert_eq!(population(&map, 2), 1);
+
+        map.get_room_mut(&3).unwrap().monsters[0].alive = false;
+
+        map.update_respawns(Instant::now() + Duration::from_secs(120), &mut rng);
+        assert_eq!(population(&map, 2), 2);
+        assert!(map.get_room(&2).unwrap().get_monsters()[1].home == Some(2));
+    }
+
+    #[test]
+    fn rooms_wait_out_the_interval_before_respawning() {
+        let mut map = house(
+            rats(0.0, 2),
+            Some(RespawnPolicy {
+                interval: Duration::from_secs(10),
+                max_population: 2,
+                only_when_empty: false,
+            }),
+        );
+        let mut rng = seeded_rng(1);
+
+        map.get_room_mut(&2).unwrap().monsters.clear();
+
+        map.update_respawns(Instant::now(), &mut rng);
+        assert_eq!(population(&map, 2), 0);
+
+        map.update_respawns(Instant::now() + Duration::from_secs(60), &mut rng);
+        assert_eq!(population(&map, 2), 2);
+    }
+
+    #[test]
+    fn certain_roamers_move_once_and_never_into_a_sanctuary() {
+        let mut map = house(rats(1.0, 3), None);
+        let mut rng = seeded_rng(1);
+
+        // The attic is walked after the cellar, so a rat placed there straight
+        // away would move on again in the same update.
+        let moves = map.update_roaming(&mut rng);
+        assert_eq!(moves.len(), 3);
+        assert!(moves.iter().all(|&(from, to, _)| from == 2 && to == 3));
+        assert_eq!(population(&map, 2), 0);
+        assert_eq!(population(&map, 3), 3);
+
+        let moves = map.update_roaming(&mut rng);
+        assert_eq!(moves.len(), 3);
+        assert_eq!(population(&map, 1), 0);
+        assert_eq!(population(&map, 2), 3);
+    }
+
+    #[test]
+    fn monsters_without_a_roam_chance_stay_put() {
+        let mut map = house(rats(0.0, 3), None);
+        let mut rng = seeded_rng(1);
+
+        for _ in 0..10 {
+            assert!(map.update_roaming(&mut rng).is_empty());
+        }
+        assert_eq!(population(&map, 2), 3);
+    }
+}
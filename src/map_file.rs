@@ -0,0 +1,436 @@
+use map::{Map, MapBuilder, RespawnPolicy};
+use monster_catalogue::MonsterCatalogue;
+use monster_spawn::monster_spawners;
+use rng::GameRng;
+use shop::ShopCatalogue;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs::File;
+use std::io::Read;
+use std::time::Duration;
+use toml;
+
+pub const DEFAULT_MAP_PATH: &str = "data/map.toml";
+
+#[derive(Debug)]
+pub enum MapLoadError {
+    Io(String, String),
+    Parse(String),
+    DuplicateRoom(String),
+    UnknownRoom(String, String),
+    SelfConnection(String),
+    UnknownSpawnTable(String, String),
+    BadPopulation(String),
+    MissingStartRoom(String),
+    UnknownShop(String, String),
+    NoRooms,
+}
+
+impl fmt::Display for MapLoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            MapLoadError::Io(ref path, ref reason) => {
+                write!(f, "Failed to read map file '{}': {}", path, reason)
+            }
+            MapLoadError::Parse(ref reason) => write!(f, "Invalid map file: {}", reason),
+            MapLoadError::DuplicateRoom(ref name) => {
+                write!(f, "Room '{}' is defined more than once.", name)
+            }
+            MapLoadError::UnknownRoom(ref from, ref to) => write!(
+                f,
+                "Room '{}' connects to '{}', which is not defined.",
+                from, to
+            ),
+            MapLoadError::SelfConnection(ref name) => {
+                write!(f, "Room '{}' cannot connect to itself.", name)
+            }
+            MapLoadError::UnknownSpawnTable(ref room, ref table) => write!(
+                f,
+                "Room '{}' uses spawn table '{}', which is not in the monster catalogue.",
+                room, table
+            ),
+            MapLoadError::BadPopulation(ref name) => write!(
+                f,
+                "Room '{}' has a minimum population above its maximum.",
+                name
+            ),
+            MapLoadError::MissingStartRoom(ref name) => {
+                write!(f, "Start room '{}' is not defined.", name)
+            }
+            MapLoadError::UnknownShop(ref room, ref shop) => write!(
+                f,
+                "Room '{}' has shop '{}', which is not defined.",
+                room, shop
+            ),
+            MapLoadError::NoRooms => write!(f, "The map does not define any rooms."),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct MapFile {
+    start_room: String,
+    // Used by rooms that don't set their own respawn policy.
+    default_respawn: Option<RespawnEntry>,
+    #[serde(default)]
+    rooms: Vec<RoomEntry>,
+}
+
+#[derive(Deserialize)]
+struct RoomEntry {
+    name: String,
+    description: String,
+    #[serde(default)]
+    connections: Vec<String>,
+    #[serde(default)]
+    spawners: Vec<SpawnerEntry>,
+    respawn: Option<RespawnEntry>,
+    #[serde(default)]
+    sanctuary: bool,
+    // Names a shop from the shop file whose keeper stands in this room.
+    shop: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct SpawnerEntry {
+    table: String,
+    // Overrides the table's own population range for this room.
+    population: Option<(u8, u8)>,
+}
+
+#[derive(Deserialize, Clone)]
+struct RespawnEntry {
+    // Seconds between respawns.
+    interval: u64,
+    max_population: usize,
+    #[serde(default)]
+    only_when_empty: bool,
+}
+
+impl RespawnEntry {
+    fn to_policy(&self) -> RespawnPolicy {
+        RespawnPolicy {
+            interval: Duration::from_secs(self.interval),
+            max_population: self.max_population,
+            only_when_empty: self.only_when_empty,
+        }
+    }
+}
+
+pub fn load_map(
+    path: &str,
+    catalogue: &MonsterCatalogue,
+    shops: &ShopCatalogue,
+    rng: &mut GameRng,
+) -> Result<Map, MapLoadError> {
+    let mut contents = String::new();
+    File::open(path)
+        .and_then(|mut file| file.read_to_string(&mut contents))
+        .map_err(|e| MapLoadError::Io(path.to_string(), e.to_string()))?;
+
+    parse_map(&contents, catalogue, shops, rng)
+}
+
+pub fn parse_map(
+    contents: &str,
+    catalogue: &MonsterCatalogue,
+    shops: &ShopCatalogue,
+    rng: &mut GameRng,
+) -> Result<Map, MapLoadError> {
+    let map_file: MapFile =
+        toml::from_str(contents).map_err(|e| MapLoadError::Parse(e.to_string()))?;
+
+    if map_file.rooms.is_empty() {
+        return Err(MapLoadError::NoRooms);
+    }
+
+    let mut map_builder = MapBuilder::new();
+    let mut room_ids: HashMap<&str, u16> = HashMap::new();
+
+    for room in map_file.rooms.iter() {
+        if room_ids.contains_key(room.name.as_str()) {
+            return Err(MapLoadError::DuplicateRoom(room.name.clone()));
+        }
+
+        let mut spawners = vec![];
+        for entry in room.spawners.iter() {
+            if let Some((min_pop, max_pop)) = entry.population {
+                if min_pop > max_pop {
+                    return Err(MapLoadError::BadPopulation(room.name.clone()));
+                }
+            }
+
+            spawners.push(
+                catalogue
+                    .spawner(&entry.table, entry.population)
+                    .ok_or_else(|| {
+                        MapLoadError::UnknownSpawnTable(room.name.clone(), entry.table.clone())
+                    })?,
+            );
+        }
+        let spawner = monster_spawners::composite_spawner(spawners);
+
+        let id = map_builder.register_room(room.name.as_str(), room.description.as_str(), spawner);
+        room_ids.insert(room.name.as_str(), id);
+
+        map_builder
+            .set_sanctuary(id, room.sanctuary)
+            .expect("Bug: Failed to set sanctuary on registered room.");
+
+        if let Some(ref shop_id) = room.shop {
+            let shop = shops
+                .get(shop_id)
+                .ok_or_else(|| MapLoadError::UnknownShop(room.name.clone(), shop_id.clone()))?;
+            map_builder
+                .set_shop(id, shop.clone())
+                .expect("Bug: Failed to set shop on registered room.");
+        }
+
+        if let Some(respawn) = room.respawn.as_ref().or(map_file.default_respawn.as_ref()) {
+            map_builder
+                .set_respawn_policy(id, respawn.to_policy())
+                .expect("Bug: Failed to set respawn policy on registered room.");
+        }
+    }
+
+    // Connections are two way, so a link listed from both rooms is only made once.
+    let mut linked: HashSet<(u16, u16)> = HashSet::new();
+
+    for room in map_file.rooms.iter() {
+        let room_id = room_ids[room.name.as_str()];
+
+        for connection in room.connections.iter() {
+            let other_id = *room_ids
+                .get(connection.as_str())
+                .ok_or_else(|| MapLoadError::UnknownRoom(room.name.clone(), connection.clone()))?;
+
+            if other_id == room_id {
+                return Err(MapLoadError::SelfConnection(room.name.clone()));
+            }
+
+            if linked.insert((room_id.min(other_id), room_id.max(other_id))) {
+                map_builder
+                    .link_rooms(room_id, other_id)
+                    .expect("Bug: Failed to link registered rooms.");
+            }
+        }
+    }
+
+    let start_id = *room_ids
+        .get(map_file.start_room.as_str())
+        .ok_or_else(|| MapLoadError::MissingStartRoom(map_file.start_room.clone()))?;
+
+    map_builder
+        .set_start_room(start_id)
+        .expect("Bug: Failed to set registered start room.");
+
+    Ok(map_builder
+        .complete(rng)
+        .expect("Bug: Failed to build map."))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use items::parse_items;
+    use monster_catalogue::parse_catalogue;
+    use rng::seeded_rng;
+    use shop::parse_shops;
+
+    fn load(contents: &str) -> Result<Map, MapLoadError> {
+        let items = parse_items("").unwrap();
+        let catalogue = parse_catalogue(
+            r#"
+            [monsters.rat]
+            name = "Rat {n}"
+            attack = 10
+            defense = 10
+            regen = 1
+            health = 20
+            gold = [0, 5]
+            description = "A rat."
+
+            [tables.rats]
+            population = [2, 2]
+            monsters = [{ template = "rat", weight = 1 }]
+            "#,
+            &items,
+        )
+        .unwrap();
+        let shops = parse_shops(
+            r#"
+            [shops.tinker]
+            keeper = "Tinker"
+            description = "Sells nothing yet."
+            "#,
+            &items,
+        )
+        .unwrap();
+
+        parse_map(contents, &catalogue, &shops, &mut seeded_rng(1))
+    }
+
+    fn load_error(contents: &str) -> String {
+        match load(contents) {
+            Ok(_) => panic!("the map should not have loaded"),
+            Err(e) => e.to_string(),
+        }
+    }
+
+    #[test]
+    fn rooms_are_linked_both_ways_and_filled() {
+        let map = load(
+            r#"
+            start_room = "Hall"
+
+            [[rooms]]
+            name = "Hall"
+            description = "A hall."
+            connections = ["Cellar"]
+            sanctuary = true
+            shop = "tinker"
+
+            [[rooms]]
+            name = "Cellar"
+            description = "A cellar."
+            connections = ["Hall"]
+            spawners = [{ table = "rats" }]
+            "#,
+        )
+        .unwrap();
+
+        let hall = map.get_start_room();
+        assert_eq!(hall.get_name(), "Hall");
+        assert!(hall.is_sanctuary());
+        assert!(hall.get_shop().is_some());
+        assert!(hall.get_monsters().is_empty());
+        assert_eq!(hall.get_adjacent_rooms().len(), 1);
+
+        let cellar = map.get_room(&hall.get_adjacent_rooms()[0]).unwrap();
+        assert_eq!(cellar.get_name(), "Cellar");
+        assert!(!cellar.is_sanctuary());
+        assert_eq!(cellar.get_adjacent_rooms(), &vec![hall.get_number()]);
+        assert_eq!(cellar.get_monsters().len(), 2);
+    }
+
+    #[test]
+    fn maps_need_rooms() {
+        assert_eq!(
+            load_error(r#"start_room = "Hall""#),
+            "The map does not define any rooms."
+        );
+    }
+
+    #[test]
+    fn room_names_must_be_unique() {
+        assert_eq!(
+            load_error(
+                r#"
+                start_room = "Hall"
+
+                [[rooms]]
+                name = "Hall"
+                description = "A hall."
+
+                [[rooms]]
+                name = "Hall"
+                description = "Another hall."
+                "#
+            ),
+            "Room 'Hall' is defined more than once."
+        );
+    }
+
+    #[test]
+    fn connections_must_lead_to_other_defined_rooms() {
+        assert_eq!(
+            load_error(
+                r#"
+                start_room = "Hall"
+
+                [[rooms]]
+                name = "Hall"
+                description = "A hall."
+                connections = ["Attic"]
+                "#
+            ),
+            "Room 'Hall' connects to 'Attic', which is not defined."
+        );
+        assert_eq!(
+            load_error(
+                r#"
+                start_room = "Hall"
+
+                [[rooms]]
+                name = "Hall"
+                description = "A hall."
+                connections = ["Hall"]
+                "#
+            ),
+            "Room 'Hall' cannot connect to itself."
+        );
+    }
+
+    #[test]
+    fn spawners_must_use_catalogue_tables_with_ordered_populations() {
+        assert_eq!(
+            load_error(
+                r#"
+                start_room = "Hall"
+
+                [[rooms]]
+                name = "Hall"
+                description = "A hall."
+                spawners = [{ table = "bats" }]
+                "#
+            ),
+            "Room 'Hall' uses spawn table 'bats', which is not in the monster catalogue."
+        );
+        assert_eq!(
+            load_error(
+                r#"
+                start_room = "Hall"
+
+                [[rooms]]
+                name = "Hall"
+                description = "A hall."
+                spawners = [{ table = "rats", population = [3, 1] }]
+                "#
+            ),
+            "Room 'Hall' has a minimum population above its maximum."
+        );
+    }
+
+    #[test]
+    fn the_start_room_must_be_defined() {
+        assert_eq!(
+            load_error(
+                r#"
+                start_room = "Porch"
+
+                [[rooms]]
+                name = "Hall"
+                description = "A hall."
+                "#
+            ),
+            "Start room 'Porch' is not defined."
+        );
+    }
+
+    #[test]
+    fn shops_must_be_defined() {
+        assert_eq!(
+            load_error(
+                r#"
+                start_room = "Hall"
+
+                [[rooms]]
+                name = "Hall"
+                description = "A hall."
+                shop = "smithy"
+                "#
+            ),
+            "Room 'Hall' has shop 'smithy', which is not defined."
+        );
+    }
+}
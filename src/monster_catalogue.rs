@@ -0,0 +1,428 @@
+use items::ItemCatalogue;
+use monster_spawn::monster_spawners;
+use monster_spawn::{MonsterSpawner, MonsterTemplate};
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::File;
+use std::io::Read;
+use toml;
+
+pub const DEFAULT_CATALOGUE_PATH: &str = "data/monsters.toml";
+
+#[derive(Debug)]
+pub enum CatalogueLoadError {
+    Io(String, String),
+    Parse(String),
+    UnknownTemplate(String, String),
+    EmptyTable(String),
+    BadPopulation(String),
+    BadGold(String),
+    UnknownItem(String, String),
+    BadDropChance(String, String),
+    BadRoamChance(String),
+    UnnumberedRoamer(String),
+}
+
+impl fmt::Display for CatalogueLoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            CatalogueLoadError::Io(ref path, ref reason) => {
+                write!(f, "Failed to read monster catalogue '{}': {}", path, reason)
+            }
+            CatalogueLoadError::Parse(ref reason) => {
+                write!(f, "Invalid monster catalogue: {}", reason)
+            }
+            CatalogueLoadError::UnknownTemplate(ref table, ref template) => write!(
+                f,
+                "Spawn table '{}' uses monster '{}', which is not defined.",
+                table, template
+            ),
+            CatalogueLoadError::EmptyTable(ref table) => write!(
+                f,
+                "Spawn table '{}' has no monsters with a weight above zero.",
+                table
+            ),
+            CatalogueLoadError::BadPopulation(ref table) => write!(
+                f,
+                "Spawn table '{}' has a minimum population above its maximum.",
+                table
+            ),
+            CatalogueLoadError::BadGold(ref monster) => write!(
+                f,
+                "Monster '{}' has a minimum gold above its maximum.",
+                monster
+            ),
+            CatalogueLoadError::UnknownItem(ref monster, ref item) => write!(
+                f,
+                "Monster '{}' drops item '{}', which is not defined.",
+                monster, item
+            ),
+            CatalogueLoadError::BadDropChance(ref monster, ref item) => write!(
+                f,
+                "Monster '{}' drops item '{}' with a chance outside 0.0 to 1.0.",
+                monster, item
+            ),
+            CatalogueLoadError::BadRoamChance(ref monster) => write!(
+                f,
+                "Monster '{}' has a roam chance outside 0.0 to 1.0.",
+                monster
+            ),
+            CatalogueLoadError::UnnumberedRoamer(ref monster) => write!(
+                f,
+                "Monster '{}' roams, so its name needs a \"{{n}}\".",
+                monster
+            ),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct CatalogueFile {
+    #[serde(default)]
+    monsters: HashMap<String, MonsterEntry>,
+    #[serde(default)]
+    tables: HashMap<String, SpawnTable>,
+}
+
+#[derive(Deserialize)]
+struct MonsterEntry {
+    #[serde(flatten)]
+    template: MonsterTemplate,
+    #[serde(default)]
+    drops: Vec<DropEntry>,
+}
+
+#[derive(Deserialize)]
+struct DropEntry {
+    item: String,
+    chance: f32,
+}
+
+#[derive(Deserialize)]
+struct SpawnTable {
+    population: (u8, u8),
+    monsters: Vec<SpawnTableEntry>,
+}
+
+#[derive(Deserialize)]
+struct SpawnTableEntry {
+    template: String,
+    weight: u32,
+}
+
+pub struct MonsterCatalogue {
+    monsters: HashMap<String, MonsterTemplate>,
+    tables: HashMap<String, (Vec<(MonsterTemplate, u32)>, (u8, u8))>,
+}
+
+impl MonsterCatalogue {
+    pub fn template(&self, id: &str) -> Option<&MonsterTemplate> {
+        self.monsters.get(id)
+    }
+
+    pub fn is_monster_name(&self, name: &str) -> bool {
+        self.monsters
+            .values()
+            .any(|template| template.matches_name(name))
+    }
+
+    pub fn spawner(&self, table: &str, population: Option<(u8, u8)>) -> Option<MonsterSpawner> {
+        self.tables
+            .get(table)
+            .map(|&(ref entries, default_population)| {
+                monster_spawners::template_spawner(
+                    entries.clone(),
+                    population.unwrap_or(default_population),
+                )
+            })
+    }
+}
+
+pub fn load_catalogue(
+    path: &str,
+    items: &ItemCatalogue,
+) -> Result<MonsterCatalogue, CatalogueLoadError> {
+    let mut contents = String::new();
+    File::open(path)
+        .and_then(|mut file| file.read_to_string(&mut contents))
+        .map_err(|e| CatalogueLoadError::Io(path.to_string(), e.to_string()))?;
+
+    parse_catalogue(&contents, items)
+}
+
+pub fn parse_catalogue(
+    contents: &str,
+    items: &ItemCatalogue,
+) -> Result<MonsterCatalogue, CatalogueLoadError> {
+    let catalogue_file: CatalogueFile =
+        toml::from_str(contents).map_err(|e| CatalogueLoadError::Parse(e.to_string()))?;
+
+    let mut monsters = HashMap::new();
+
+    for (id, entry) in catalogue_file.monsters.into_iter() {
+        let mut template = entry.template;
+
+        if template.gold.0 > template.gold.1 {
+            return Err(CatalogueLoadError::BadGold(id));
+        }
+
+        if template.roam_chance < 0.0 || template.roam_chance > 1.0 {
+            return Err(CatalogueLoadError::BadRoamChance(id));
+        }
+
+        // Roamers end up alongside other rooms' monsters, so need unique names.
+        if template.roam_chance > 0.0 && !template.has_number() {
+            return Err(CatalogueLoadError::UnnumberedRoamer(id));
+        }
+
+        for drop in entry.drops.iter() {
+            let item = items
+                .get(&drop.item)
+                .ok_or_else(|| CatalogueLoadError::UnknownItem(id.clone(), drop.item.clone()))?;
+
+            if drop.chance < 0.0 || drop.chance > 1.0 {
+                return Err(CatalogueLoadError::BadDropChance(
+                    id.clone(),
+                    drop.item.clone(),
+                ));
+            }
+
+            template.drops.push((item.clone(), drop.chance));
+        }
+
+        monsters.insert(id, template);
+    }
+
+    let mut tables = HashMap::new();
+
+    for (table_id, table) in catalogue_file.tables.iter() {
+        if table.population.0 > table.population.1 {
+            return Err(CatalogueLoadError::BadPopulation(table_id.clone()));
+        }
+
+        let mut entries = vec![];
+        for entry in table.monsters.iter() {
+            let template = monsters.get(&entry.template).ok_or_else(|| {
+                CatalogueLoadError::UnknownTemplate(table_id.clone(), entry.template.clone())
+            })?;
+
+            if entry.weight > 0 {
+                entries.push((template.clone(), entry.weight));
+            }
+        }
+
+        if entries.is_empty() {
+            return Err(CatalogueLoadError::EmptyTable(table_id.clone()));
+        }
+
+        tables.insert(table_id.clone(), (entries, table.population));
+    }
+
+    Ok(MonsterCatalogue { monsters, tables })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use items::parse_items;
+
+    const RAT: &str = r#"
+        [monsters.rat]
+        name = "Rat {n}"
+        attack = 10
+        defense = 10
+        regen = 1
+        health = 20
+        gold = [0, 5]
+        description = "A rat."
+        roam_chance = 0.5
+    "#;
+
+    fn load(contents: &str) -> Result<MonsterCatalogue, CatalogueLoadError> {
+        let items = parse_items(
+            r#"
+            [items.cheese]
+            name = "Cheese"
+            kind = "potion"
+            heal = 10
+            description = "Smells strongly."
+            "#,
+        )
+        .unwrap();
+
+        parse_catalogue(&format!("{}{}", RAT, contents), &items)
+    }
+
+    fn load_error(contents: &str) -> String {
+        match load(contents) {
+            Ok(_) => panic!("the catalogue should not have loaded"),
+            Err(e) => e.to_string(),
+        }
+    }
+
+    #[test]
+    fn tables_draw_from_defined_monsters() {
+        let catalogue = load(
+            r#"
+            [tables.rats]
+            population = [1, 3]
+            monsters = [{ template = "rat", weight = 1 }]
+            "#,
+        )
+        .unwrap();
+
+        assert!(catalogue.template("rat").is_some());
+        assert!(catalogue.is_monster_name("rat 12"));
+        assert!(catalogue.spawner("rats", None).is_some());
+        assert!(catalogue.spawner("bats", None).is_none());
+    }
+
+    #[test]
+    fn tables_must_name_defined_monsters() {
+        assert_eq!(
+            load_error(
+                r#"
+                [tables.rats]
+                population = [1, 3]
+                monsters = [{ template = "bat", weight = 1 }]
+                "#
+            ),
+            "Spawn table 'rats' uses monster 'bat', which is not defined."
+        );
+    }
+
+    #[test]
+    fn tables_need_a_weighted_monster() {
+        let expected = "Spawn table 'rats' has no monsters with a weight above zero.";
+
+        assert_eq!(
+            load_error(
+                r#"
+                [tables.rats]
+                population = [1, 3]
+                monsters = []
+                "#
+            ),
+            expected
+        );
+        assert_eq!(
+            load_error(
+                r#"
+                [tables.rats]
+                population = [1, 3]
+                monsters = [{ template = "rat", weight = 0 }]
+                "#
+            ),
+            expected
+        );
+    }
+
+    #[test]
+    fn table_population_ranges_must_be_ordered() {
+        assert_eq!(
+            load_error(
+                r#"
+                [tables.rats]
+                population = [3, 1]
+                monsters = [{ template = "rat", weight = 1 }]
+                "#
+            ),
+            "Spawn table 'rats' has a minimum population above its maximum."
+        );
+    }
+
+    #[test]
+    fn gold_ranges_must_be_ordered() {
+        assert_eq!(
+            load_error(
+                r#"
+                [monsters.miser]
+                name = "Miser"
+                attack = 10
+                defense = 10
+                regen = 1
+                health = 20
+                gold = [50, 5]
+                description = "Counts coins."
+                "#
+            ),
+            "Monster 'miser' has a minimum gold above its maximum."
+        );
+    }
+
+    #[test]
+    fn drops_must_be_defined_items_with_a_sensible_chance() {
+        assert_eq!(
+            load_error(
+                r#"
+                [monsters.mouse]
+                name = "Mouse"
+                attack = 10
+                defense = 10
+                regen = 1
+                health = 20
+                gold = [0, 5]
+                description = "A mouse."
+                drops = [{ item = "crumbs", chance = 0.5 }]
+                "#
+            ),
+            "Monster 'mouse' drops item 'crumbs', which is not defined."
+        );
+        assert_eq!(
+            load_error(
+                r#"
+                [monsters.mouse]
+                name = "Mouse"
+                attack = 10
+                defense = 10
+                regen = 1
+                health = 20
+                gold = [0, 5]
+                description = "A mouse."
+                drops = [{ item = "cheese", chance = 1.5 }]
+                "#
+            ),
+            "Monster 'mouse' drops item 'cheese' with a chance outside 0.0 to 1.0."
+        );
+    }
+
+    #[test]
+    fn roam_chances_must_be_sensible() {
+        assert_eq!(
+            load_error(
+                r#"
+                [monsters.bat]
+                name = "Bat {n}"
+                attack = 10
+                defense = 10
+                regen = 1
+                health = 20
+                gold = [0, 5]
+                description = "A bat."
+                roam_chance = -0.1
+                "#
+            ),
+            "Monster 'bat' has a roam chance outside 0.0 to 1.0."
+        );
+    }
+
+    #[test]
+    fn roamers_need_numbered_names() {
+        assert_eq!(
+            load_error(
+                r#"
+                [monsters.bat]
+                name = "Bat"
+                attack = 10
+                defense = 10
+                regen = 1
+                health = 20
+                gold = [0, 5]
+                description = "A bat."
+                roam_chance = 0.2
+                "#
+            ),
+            "Monster 'bat' roams, so its name needs a \"{n}\"."
+        );
+    }
+}
@@ -0,0 +1,47 @@
+use admin::SERVER_NAME;
+use chat::{ALL_CHANNEL, ROOM_CHANNEL};
+
+// Names travel in fixed 32 byte fields.
+pub const NAME_LIMIT: usize = 32;
+
+// Names that messages are routed by, so no player may take them.
+const RESERVED_NAMES: [&str; 3] = [SERVER_NAME, ROOM_CHANNEL, ALL_CHANNEL];
+
+// Checks the shape of a character name: a letter followed by letters, digits,
+// spaces, hyphens, apostrophes or underscores.
+pub fn check_format(name: &str) -> Result<(), String> {
+    if name.is_empty() {
+        return Err("Your character needs a name.".to_string());
+    }
+
+    if name.len() > NAME_LIMIT {
+        return Err(format!(
+            "Names can be at most {} characters long.",
+            NAME_LIMIT
+        ));
+    }
+
+    if !name.starts_with(|c: char| c.is_ascii_alphabetic()) {
+        return Err("Names must start with a letter.".to_string());
+    }
+
+    if name.ends_with(' ') || name.contains("  ") {
+        return Err("Names can't end in a space or have spaces in a row.".to_string());
+    }
+
+    let allowed = |c: char| c.is_ascii_alphanumeric() || " -'_".contains(c);
+    if !name.chars().all(allowed) {
+        return Err(
+            "Names may only use letters, digits, spaces, hyphens, apostrophes and underscores."
+                .to_string(),
+        );
+    }
+
+    Ok(())
+}
+
+pub fn is_reserved(name: &str) -> bool {
+    RESERVED_NAMES
+        .iter()
+        .any(|reserved| reserved.eq_ignore_ascii_case(name))
+}
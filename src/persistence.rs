@@ -0,0 +1,169 @@
+use entity::{Aggression, Entity};
+use items::Inventory;
+use serde::Serialize;
+use serde_json;
+use std::collections::HashMap;
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+pub const DEFAULT_STORE_PATH: &str = "saves/characters.json";
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SavedCharacter {
+    pub name: String,
+    pub attack: u16,
+    pub defense: u16,
+    pub regen: u16,
+    pub health: i16,
+    pub base_health: i16,
+    pub gold: u16,
+    pub location: u16,
+    pub alive: bool,
+    pub desc: String,
+    // Saves from before leveling existed start at level 1.
+    #[serde(default)]
+    pub experience: u32,
+    #[serde(default = "first_level")]
+    pub level: u16,
+    #[serde(default)]
+    pub stat_points: u16,
+    #[serde(default)]
+    pub inventory: Inventory,
+}
+
+fn first_level() -> u16 {
+    1
+}
+
+impl SavedCharacter {
+    pub fn from_entity(entity: &Entity) -> SavedCharacter {
+        SavedCharacter {
+            name: entity.name.clone(),
+            attack: entity.attack,
+            defense: entity.defense,
+            regen: entity.regen,
+            health: entity.health,
+            base_health: entity.base_health,
+            gold: entity.gold,
+            location: entity.location,
+            alive: entity.alive,
+            desc: entity.desc.clone(),
+            experience: entity.experience,
+            level: entity.level,
+            stat_points: entity.stat_points,
+            inventory: entity.inventory.clone(),
+        }
+    }
+
+    pub fn to_entity(&self) -> Entity {
+        Entity {
+            update_dirty: false,
+            name: self.name.clone(),
+            attack: self.attack,
+            defense: self.defense,
+            regen: self.regen,
+            health: self.health,
+            gold: self.gold,
+            location: self.location,
+            alive: self.alive,
+            monster: false,
+            desc: self.desc.clone(),
+            base_health: self.base_health,
+            experience: self.experience,
+            level: self.level,
+            stat_points: self.stat_points,
+            inventory: self.inventory.clone(),
+            roam_chance: 0.0,
+            aggression: Aggression::Never,
+            hostile: false,
+            home: None,
+        }
+    }
+}
+
+// Characters keyed by name, kept in memory and written out as a whole on every
+// save.
+pub struct CharacterStore {
+    path: PathBuf,
+    characters: Mutex<HashMap<String, SavedCharacter>>,
+}
+
+impl CharacterStore {
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<CharacterStore> {
+        let path = path.as_ref().to_path_buf();
+
+        let characters = if path.exists() {
+            let mut contents = String::new();
+            File::open(&path)?.read_to_string(&mut contents)?;
+            serde_json::from_str(&contents)?
+        } else {
+            HashMap::new()
+        };
+
+        Ok(CharacterStore {
+            path,
+            characters: Mutex::new(characters),
+        })
+    }
+
+    pub fn load(&self, name: &str) -> Option<SavedCharacter> {
+        self.characters.lock().unwrap().get(name).cloned()
+    }
+
+    // The saved name matching this one when case is ignored, if any.
+    pub fn find_name(&self, name: &str) -> Option<String> {
+        self.characters
+            .lock()
+            .unwrap()
+            .keys()
+            .find(|saved| saved.eq_ignore_ascii_case(name))
+            .cloned()
+    }
+
+    pub fn remove(&self, name: &str) -> io::Result<()> {
+        let mut characters = self.characters.lock().unwrap();
+
+        if characters.remove(name).is_some() {
+            self.write(&characters)
+        } else {
+            Ok(())
+        }
+    }
+
+    pub fn save_characters(&self, entities: &[&Entity]) -> io::Result<()> {
+        let mut characters = self.characters.lock().unwrap();
+
+        for entity in entities.iter() {
+            characters.insert(entity.name.clone(), SavedCharacter::from_entity(entity));
+        }
+
+        // The lock is held while writing so concurrent saves can't interleave.
+        self.write(&characters)
+    }
+
+    fn write(&self, characters: &HashMap<String, SavedCharacter>) -> io::Result<()> {
+        write_json(&self.path, characters)
+    }
+}
+
+// Writes to a temporary file that is then renamed over the target, so a crash
+// mid-write leaves the previous contents intact.
+pub fn write_json<T: Serialize>(path: &Path, value: &T) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+        if !parent.as_os_str().is_empty() {
+            fs::create_dir_all(parent)?;
+        }
+    }
+
+    let temp_path = path.with_extension("tmp");
+    {
+        let mut file = File::create(&temp_path)?;
+        file.write_all(serde_json::to_string_pretty(value)?.as_bytes())?;
+        file.sync_all()?;
+    }
+    fs::rename(&temp_path, path)
+}
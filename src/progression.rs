@@ -0,0 +1,140 @@
+use entity::Entity;
+
+// Killing a monster is worth a tenth of its combined stats and health.
+pub fn kill_experience(monster: &Entity) -> u32 {
+    let toughness = monster.attack as u32
+        + monster.defense as u32
+        + monster.regen as u32
+        + monster.base_health.max(0) as u32;
+    (toughness / 10).max(1)
+}
+
+// Total experience needed to reach a level: 100 for level 2, 300 for level 3,
+// 600 for level 4 and so on.
+pub fn experience_for_level(level: u16) -> u32 {
+    let level = level as u32;
+    50 * level * level.saturating_sub(1)
+}
+
+// Raises the entity's level for as long as it has the experience for the next
+// one, granting stat points for each. Returns the number of levels gained.
+pub fn level_up(entity: &mut Entity, points_per_level: u16) -> u16 {
+    let mut gained = 0;
+
+    while entity.level < u16::max_value()
+        && entity.experience >= experience_for_level(entity.level + 1)
+    {
+        entity.level += 1;
+        entity.stat_points = entity.stat_points.saturating_add(points_per_level);
+        gained += 1;
+    }
+
+    if gained > 0 {
+        entity.update_dirty = true;
+    }
+
+    gained
+}
+
+// Raises the entity's stats to the totals a player asks for, paid for with the
+// points earned by leveling. Stats can't be lowered, and asking for the stats
+// as they are spends nothing. Returns the points spent.
+pub fn raise_stats(
+    entity: &mut Entity,
+    attack: u16,
+    defense: u16,
+    regen: u16,
+    stat_limit: u16,
+) -> Result<u16, String> {
+    let current = [entity.attack, entity.defense, entity.regen];
+    let wanted = [attack, defense, regen];
+    let changes: Vec<(u16, u16)> = current
+        .iter()
+        .cloned()
+        .zip(wanted.iter().cloned())
+        .collect();
+
+    if changes.iter().any(|&(current, wanted)| wanted < current) {
+        return Err("Stats can't be lowered.".to_string());
+    }
+
+    if changes
+        .iter()
+        .any(|&(current, wanted)| wanted > current && wanted > stat_limit)
+    {
+        return Err("One or more attributes were set too high.".to_string());
+    }
+
+    let cost: u32 = changes
+        .iter()
+        .map(|&(current, wanted)| (wanted - current) as u32)
+        .sum();
+    if cost > entity.stat_points as u32 {
+        return Err(format!(
+            "You only have {} stat points to spend.",
+            entity.stat_points
+        ));
+    }
+
+    entity.attack = attack;
+    entity.defense = defense;
+    entity.regen = regen;
+    entity.stat_points -= cost as u16;
+    if cost > 0 {
+        entity.update_dirty = true;
+    }
+
+    Ok(cost as u16)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use entity::test_entity;
+
+    #[test]
+    fn stats_are_raised_to_the_totals_asked_for() {
+        let mut player = test_entity("Novice", false);
+        player.stat_points = 30;
+
+        assert_eq!(raise_stats(&mut player, 110, 100, 120, 500), Ok(30));
+        assert_eq!(
+            (player.attack, player.defense, player.regen),
+            (110, 100, 120)
+        );
+        assert_eq!(player.stat_points, 0);
+    }
+
+    #[test]
+    fn resent_stats_spend_nothing() {
+        let mut player = test_entity("Novice", false);
+
+        assert_eq!(raise_stats(&mut player, 100, 100, 100, 500), Ok(0));
+        assert!(!player.update_dirty);
+    }
+
+    #[test]
+    fn stats_cannot_be_lowered_or_overspent() {
+        let mut player = test_entity("Novice", false);
+        player.stat_points = 10;
+
+        assert!(raise_stats(&mut player, 120, 90, 100, 500).is_err());
+        assert!(raise_stats(&mut player, 111, 100, 100, 500).is_err());
+        assert!(raise_stats(&mut player, 105, 100, 100, 104).is_err());
+        assert_eq!(
+            (player.attack, player.defense, player.regen),
+            (100, 100, 100)
+        );
+        assert_eq!(player.stat_points, 10);
+    }
+
+    #[test]
+    fn experience_levels_up_and_grants_points() {
+        let mut player = test_entity("Novice", false);
+        player.experience = experience_for_level(3);
+
+        assert_eq!(level_up(&mut player, 30), 2);
+        assert_eq!(player.level, 3);
+        assert_eq!(player.stat_points, 60);
+    }
+}
@@ -0,0 +1,167 @@
+use std::collections::{HashMap, HashSet};
+use std::time::Instant;
+use uuid::Uuid;
+
+// The packets a client could flood the server with, each limited separately.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PacketKind {
+    Fight,
+    Loot,
+    Message,
+    ChangeRoom,
+    Character,
+    Start,
+}
+
+impl PacketKind {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            PacketKind::Fight => "fight",
+            PacketKind::Loot => "loot",
+            PacketKind::Message => "message",
+            PacketKind::ChangeRoom => "change room",
+            PacketKind::Character => "character",
+            PacketKind::Start => "start",
+        }
+    }
+}
+
+// A token bucket: up to `burst` packets at once, refilled at `per_second`.
+// A burst of zero turns the limit off.
+#[derive(Clone, Copy)]
+pub struct Limit {
+    pub burst: u32,
+    pub per_second: f64,
+}
+
+struct Bucket {
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+#[derive(Clone, Default, Serialize)]
+pub struct KindCounters {
+    pub allowed: u64,
+    pub limited: u64,
+}
+
+// Totals since the server started, for the REST service.
+#[derive(Clone, Default, Serialize)]
+pub struct LimitCounters {
+    pub fight: KindCounters,
+    pub loot: KindCounters,
+    pub message: KindCounters,
+    pub change_room: KindCounters,
+    pub character: KindCounters,
+    pub start: KindCounters,
+    // Clients dropped for going over their limits too often.
+    pub dropped: u64,
+}
+
+impl LimitCounters {
+    fn kind_mut(&mut self, kind: PacketKind) -> &mut KindCounters {
+        match kind {
+            PacketKind::Fight => &mut self.fight,
+            PacketKind::Loot => &mut self.loot,
+            PacketKind::Message => &mut self.message,
+            PacketKind::ChangeRoom => &mut self.change_room,
+            PacketKind::Character => &mut self.character,
+            PacketKind::Start => &mut self.start,
+        }
+    }
+}
+
+pub enum RateDecision {
+    Allowed,
+    Limited,
+    // The client kept sending while limited and should be dropped. Anything
+    // else it sends on this connection is refused, whatever its kind.
+    Dropped,
+    // The client was dropped or kicked earlier.
+    Refused,
+}
+
+pub struct PacketLimiter {
+    limits: HashMap<PacketKind, Limit>,
+    // Limited packets in a row before a client is dropped. Zero never drops.
+    max_strikes: u32,
+    buckets: HashMap<(Uuid, PacketKind), Bucket>,
+    strikes: HashMap<Uuid, u32>,
+    dropped: HashSet<Uuid>,
+    counters: LimitCounters,
+}
+
+impl PacketLimiter {
+    pub fn new(limits: Vec<(PacketKind, Limit)>, max_strikes: u32) -> PacketLimiter {
+        PacketLimiter {
+            limits: limits.into_iter().collect(),
+            max_strikes,
+            buckets: HashMap::new(),
+            strikes: HashMap::new(),
+            dropped: HashSet::new(),
+            counters: LimitCounters::default(),
+        }
+    }
+
+    // Takes a token for the packet if the client has one. Any allowed packet
+    // clears the client's strikes, so only a client that keeps hammering away
+    // while limited is dropped.
+    pub fn check(&mut self, client_id: Uuid, kind: PacketKind, now: Instant) -> RateDecision {
+        if self.dropped.contains(&client_id) {
+            return RateDecision::Refused;
+        }
+
+        let limit = match self.limits.get(&kind) {
+            Some(limit) if limit.burst > 0 => *limit,
+            _ => return RateDecision::Allowed,
+        };
+
+        let bucket = self
+            .buckets
+            .entry((client_id, kind))
+            .or_insert_with(|| Bucket {
+                tokens: limit.burst as f64,
+                refilled_at: now,
+            });
+
+        let elapsed = now.duration_since(bucket.refilled_at);
+        let elapsed = elapsed.as_secs() as f64 + elapsed.subsec_nanos() as f64 / 1e9;
+        bucket.tokens = (bucket.tokens + elapsed * limit.per_second).min(limit.burst as f64);
+        bucket.refilled_at = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            self.counters.kind_mut(kind).allowed += 1;
+            self.strikes.remove(&client_id);
+            return RateDecision::Allowed;
+        }
+
+        self.counters.kind_mut(kind).limited += 1;
+        let strikes = self.strikes.entry(client_id).or_insert(0);
+        *strikes += 1;
+
+        if self.max_strikes > 0 && *strikes >= self.max_strikes {
+            self.dropped.insert(client_id);
+            self.counters.dropped += 1;
+            return RateDecision::Dropped;
+        }
+
+        RateDecision::Limited
+    }
+
+    // Refuses everything the client sends until it disconnects, as for a
+    // client dropped for flooding.
+    pub fn block(&mut self, client_id: Uuid) {
+        self.dropped.insert(client_id);
+    }
+
+    pub fn counters(&self) -> &LimitCounters {
+        &self.counters
+    }
+
+    pub fn forget(&mut self, client_id: &Uuid) {
+        self.buckets.retain(|&(id, _), _| id != *client_id);
+        self.strikes.remove(client_id);
+        self.dropped.remove(client_id);
+    }
+}
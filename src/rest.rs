@@ -1,9 +1,15 @@
-use map::Map;
+use accounts::{self, AccountStore};
+use combat::CombatLog;
+use entity::Entity;
+use map::{Map, Room};
+use nickel::status::StatusCode;
 use nickel::Request;
-use nickel::{MediaType, Nickel};
+use nickel::{MediaType, Nickel, Options};
+use rate_limit::PacketLimiter;
+use serde_json;
 use std::collections::HashMap;
-use std::net::IpAddr;
-use std::net::Ipv4Addr;
+use std::error::Error;
+use std::net::SocketAddr;
 use std::sync::{Arc, Mutex};
 use uuid::Uuid;
 use Player;
@@ -17,8 +23,13 @@ impl RestService {
     pub fn new(
         map: Arc<Mutex<Map>>,
         players: Arc<Mutex<HashMap<Uuid, Player>>>,
+        combat_log: Arc<Mutex<CombatLog>>,
+        packet_limiter: Arc<Mutex<PacketLimiter>>,
+        accounts: Arc<AccountStore>,
+        admin_token: Option<String>,
     ) -> Result<RestService, ()> {
         let mut server = Nickel::new();
+        server.options = Options::default().output_on_listen(false);
 
         let pplayers = players.clone();
         let pmap = map.clone();
@@ -38,13 +49,79 @@ impl RestService {
             }
         });
 
+        let aplayers = players.clone();
+        let amap = map.clone();
+        server.utilize(router! {
+            get "/api/players" => |_request, mut response| {
+                response.set(MediaType::Json);
+                get_players_json(aplayers.clone(), amap.clone())
+            }
+        });
+
+        let aplayers = players.clone();
+        let amap = map.clone();
+        server.utilize(router! {
+            get "/api/players/:id" => |request, mut response| {
+                response.set(MediaType::Json);
+                get_player_json(request.param("id").unwrap_or(""), aplayers.clone(), amap.clone())
+            }
+        });
+
+        let aplayers = players.clone();
+        let amap = map.clone();
+        server.utilize(router! {
+            get "/api/rooms" => |_request, mut response| {
+                response.set(MediaType::Json);
+                get_rooms_json(aplayers.clone(), amap.clone())
+            }
+        });
+
+        let aplayers = players.clone();
+        let amap = map.clone();
+        server.utilize(router! {
+            get "/api/rooms/:number" => |request, mut response| {
+                response.set(MediaType::Json);
+                get_room_json(request.param("number").unwrap_or(""), aplayers.clone(), amap.clone())
+            }
+        });
+
+        server.utilize(router! {
+            get "/api/combat" => |_request, mut response| {
+                response.set(MediaType::Json);
+                get_combat_report(combat_log.clone())
+            }
+        });
+
+        server.utilize(router! {
+            get "/api/limits" => |_request, mut response| {
+                response.set(MediaType::Json);
+                get_limits_report(packet_limiter.clone())
+            }
+        });
+
+        server.utilize(router! {
+            post "/api/accounts/:name/reset" => |request, mut response| {
+                response.set(MediaType::Json);
+                let token = bearer_token(request);
+                reset_account(
+                    request.param("name").unwrap_or(""),
+                    token,
+                    admin_token.as_ref(),
+                    accounts.clone(),
+                )
+            }
+        });
+
         Ok(RestService { server })
     }
 
-    pub fn start(self, port: u16) {
-        self.server
-            .listen((IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)), port))
-            .unwrap();
+    // Binds the service and hands it off to a background thread, returning the
+    // address it ended up listening on.
+    pub fn start(self, addr: SocketAddr) -> Result<SocketAddr, Box<Error>> {
+        let listening = self.server.listen(addr)?;
+        let local_addr = listening.socket();
+        listening.detach();
+        Ok(local_addr)
     }
 }
 
@@ -170,3 +247,346 @@ fn get_players_report(
         get_all_players_report(players)
     }
 }
+
+fn get_combat_report(combat_log: Arc<Mutex<CombatLog>>) -> String {
+    let guard = combat_log.lock().unwrap();
+    serde_json::to_string(guard.records()).expect("Failed to serialize combat log.")
+}
+
+#[derive(Serialize)]
+struct RoomRefView {
+    number: u16,
+    name: String,
+}
+
+fn get_limits_report(packet_limiter: Arc<Mutex<PacketLimiter>>) -> String {
+    let guard = packet_limiter.lock().unwrap();
+    serde_json::to_string(guard.counters()).expect("Failed to serialize rate limit counters.")
+}
+
+#[derive(Serialize)]
+struct PlayerView {
+    id: String,
+    name: String,
+    description: String,
+    attack: u16,
+    defense: u16,
+    regen: u16,
+    health: i16,
+    max_health: i16,
+    gold: u16,
+    level: u16,
+    experience: u32,
+    stat_points: u16,
+    weapon: Option<String>,
+    armour: Option<String>,
+    items: Vec<String>,
+    alive: bool,
+    ready: bool,
+    started: bool,
+    join_battle: bool,
+    // Disconnected, but still in the world waiting for the player to return.
+    linkdead: bool,
+    afk: bool,
+    location: Option<RoomRefView>,
+}
+
+#[derive(Serialize)]
+struct OccupantView {
+    id: String,
+    name: String,
+}
+
+#[derive(Serialize)]
+struct MonsterView {
+    name: String,
+    description: String,
+    attack: u16,
+    defense: u16,
+    regen: u16,
+    health: i16,
+    max_health: i16,
+    gold: u16,
+    alive: bool,
+}
+
+#[derive(Serialize)]
+struct RoomView {
+    number: u16,
+    name: String,
+    description: String,
+    sanctuary: bool,
+    players: Vec<OccupantView>,
+    monsters: Vec<MonsterView>,
+    adjacent: Vec<RoomRefView>,
+}
+
+#[derive(Serialize)]
+struct ErrorView {
+    error: String,
+}
+
+fn error_json(status: StatusCode, error: &str) -> (StatusCode, String) {
+    let view = ErrorView {
+        error: error.to_string(),
+    };
+    (
+        status,
+        serde_json::to_string(&view).expect("Failed to serialize error."),
+    )
+}
+
+fn room_ref_view(map: &Map, room_number: u16) -> Option<RoomRefView> {
+    map.get_room(&room_number).map(|room| RoomRefView {
+        number: room.get_number(),
+        name: room.get_name(),
+    })
+}
+
+fn player_view(player: &Player, map: &Map) -> PlayerView {
+    let entity = &player.entity_info;
+    PlayerView {
+        id: player.id.to_string(),
+        name: entity.name.clone(),
+        description: entity.desc.clone(),
+        attack: entity.get_effective_attack(),
+        defense: entity.get_effective_defense(),
+        regen: entity.get_effective_regen(),
+        health: entity.health,
+        max_health: entity.get_max_health(),
+        gold: entity.gold,
+        level: entity.level,
+        experience: entity.experience,
+        stat_points: entity.stat_points,
+        weapon: entity
+            .inventory
+            .weapon
+            .as_ref()
+            .map(|item| item.name.clone()),
+        armour: entity
+            .inventory
+            .armour
+            .as_ref()
+            .map(|item| item.name.clone()),
+        items: entity
+            .inventory
+            .items
+            .iter()
+            .map(|item| item.name.clone())
+            .collect(),
+        alive: entity.alive,
+        ready: player.ready,
+        started: player.started,
+        join_battle: player.join_battle,
+        linkdead: player.is_linkdead(),
+        afk: player.afk,
+        location: if player.started {
+            room_ref_view(map, entity.location)
+        } else {
+            None
+        },
+    }
+}
+
+fn monster_view(monster: &Entity) -> MonsterView {
+    MonsterView {
+        name: monster.name.clone(),
+        description: monster.desc.clone(),
+        attack: monster.attack,
+        defense: monster.defense,
+        regen: monster.regen,
+        health: monster.health,
+        max_health: monster.get_max_health(),
+        gold: monster.gold,
+        alive: monster.alive,
+    }
+}
+
+fn room_view(room: &Room, players: &HashMap<Uuid, Player>, map: &Map) -> RoomView {
+    RoomView {
+        number: room.get_number(),
+        name: room.get_name(),
+        description: room.get_description(),
+        sanctuary: room.is_sanctuary(),
+        players: room
+            .get_player_ids()
+            .iter()
+            .filter_map(|id| players.get(id))
+            .map(|player| OccupantView {
+                id: player.id.to_string(),
+                name: player.entity_info.name.clone(),
+            })
+            .collect(),
+        monsters: room.get_monsters().iter().map(monster_view).collect(),
+        adjacent: room
+            .get_adjacent_rooms()
+            .iter()
+            .filter_map(|number| room_ref_view(map, *number))
+            .collect(),
+    }
+}
+
+fn get_players_json(
+    players: Arc<Mutex<HashMap<Uuid, Player>>>,
+    map: Arc<Mutex<Map>>,
+) -> (StatusCode, String) {
+    let players_guard = players.lock().unwrap();
+    let map_guard = map.lock().unwrap();
+
+    let views: Vec<PlayerView> = players_guard
+        .values()
+        .map(|player| player_view(player, &map_guard))
+        .collect();
+
+    (
+        StatusCode::Ok,
+        serde_json::to_string(&views).expect("Failed to serialize players."),
+    )
+}
+
+fn get_player_json(
+    player_id: &str,
+    players: Arc<Mutex<HashMap<Uuid, Player>>>,
+    map: Arc<Mutex<Map>>,
+) -> (StatusCode, String) {
+    let uuid = match Uuid::from_str(player_id) {
+        Ok(uuid) => uuid,
+        Err(_) => return error_json(StatusCode::BadRequest, "Invalid id."),
+    };
+
+    let players_guard = players.lock().unwrap();
+    let map_guard = map.lock().unwrap();
+
+    match players_guard.get(&uuid) {
+        Some(player) => (
+            StatusCode::Ok,
+            serde_json::to_string(&player_view(player, &map_guard))
+                .expect("Failed to serialize player."),
+        ),
+        None => error_json(StatusCode::NotFound, "No such player exists."),
+    }
+}
+
+fn get_rooms_json(
+    players: Arc<Mutex<HashMap<Uuid, Player>>>,
+    map: Arc<Mutex<Map>>,
+) -> (StatusCode, String) {
+    let players_guard = players.lock().unwrap();
+    let map_guard = map.lock().unwrap();
+
+    let views: Vec<RoomView> = map_guard
+        .get_room_numbers()
+        .iter()
+        .filter_map(|number| map_guard.get_room(number))
+        .map(|room| room_view(room, &players_guard, &map_guard))
+        .collect();
+
+    (
+        StatusCode::Ok,
+        serde_json::to_string(&views).expect("Failed to serialize rooms."),
+    )
+}
+
+fn get_room_json(
+    room_number: &str,
+    players: Arc<Mutex<HashMap<Uuid, Player>>>,
+    map: Arc<Mutex<Map>>,
+) -> (StatusCode, String) {
+    let number = match room_number.parse::<u16>() {
+        Ok(number) => number,
+        Err(_) => return error_json(StatusCode::BadRequest, "Invalid room number."),
+    };
+
+    let players_guard = players.lock().unwrap();
+    let map_guard = map.lock().unwrap();
+
+    match map_guard.get_room(&number) {
+        Some(room) => (
+            StatusCode::Ok,
+            serde_json::to_string(&room_view(room, &players_guard, &map_guard))
+                .expect("Failed to serialize room."),
+        ),
+        None => error_json(StatusCode::NotFound, "No such room exists."),
+    }
+}
+
+#[derive(Serialize)]
+struct ResetView {
+    name: String,
+    reset: bool,
+}
+
+// The token from an "Authorization: Bearer <token>" header.
+fn bearer_token(request: &Request) -> Option<String> {
+    let value = request.origin.headers.get_raw("Authorization")?.first()?;
+    let value = String::from_utf8(value.clone()).ok()?;
+
+    value
+        .strip_prefix("Bearer ")
+        .map(|token| token.trim().to_string())
+}
+
+// Names may contain spaces, which arrive in the path as "%20".
+fn percent_decode(text: &str) -> Option<String> {
+    let mut bytes = vec![];
+    let mut rest = text.as_bytes();
+
+    while let Some((&byte, tail)) = rest.split_first() {
+        if byte == b'%' {
+            let hex = tail
+                .get(..2)
+                .and_then(|hex| ::std::str::from_utf8(hex).ok())?;
+            bytes.push(u8::from_str_radix(hex, 16).ok()?);
+            rest = &tail[2..];
+        } else {
+            bytes.push(byte);
+            rest = tail;
+        }
+    }
+
+    String::from_utf8(bytes).ok()
+}
+
+fn reset_account(
+    name: &str,
+    token: Option<String>,
+    admin_token: Option<&String>,
+    accounts: Arc<AccountStore>,
+) -> (StatusCode, String) {
+    let admin_token = match admin_token {
+        Some(admin_token) => admin_token,
+        None => return error_json(StatusCode::NotFound, "Admin endpoints are disabled."),
+    };
+
+    let authorized = token.map_or(false, |token| {
+        accounts::constant_time_eq(token.as_bytes(), admin_token.as_bytes())
+    });
+    if !authorized {
+        return error_json(StatusCode::Unauthorized, "Missing or wrong admin token.");
+    }
+
+    let name = match percent_decode(name) {
+        Some(name) => name,
+        None => return error_json(StatusCode::BadRequest, "Invalid name."),
+    };
+    let name = name.as_str();
+
+    match accounts.reset(name) {
+        Ok(true) => {
+            println!("Password for {} reset over REST.", name);
+            (
+                StatusCode::Ok,
+                serde_json::to_string(&ResetView {
+                    name: name.to_string(),
+                    reset: true,
+                })
+                .expect("Failed to serialize reset."),
+            )
+        }
+        Ok(false) => error_json(StatusCode::NotFound, "No such account exists."),
+        Err(e) => {
+            println!("Failed to reset account {}: {}", name, e);
+            error_json(StatusCode::InternalServerError, "Failed to save accounts.")
+        }
+    }
+}
@@ -0,0 +1,16 @@
+use rand::{thread_rng, Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+// The single source of randomness for the game world. Everything that rolls
+// dice takes one of these, so a seed plus the same inputs replays a session.
+// The algorithm is named, and pinned in Cargo.toml, because StdRng's stream can
+// change between rand releases.
+pub type GameRng = ChaCha8Rng;
+
+pub fn random_seed() -> u64 {
+    thread_rng().gen()
+}
+
+pub fn seeded_rng(seed: u64) -> GameRng {
+    GameRng::seed_from_u64(seed)
+}
@@ -0,0 +1,264 @@
+use entity::Entity;
+use items::{Item, ItemCatalogue};
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::File;
+use std::io::Read;
+use toml;
+
+pub const DEFAULT_SHOPS_PATH: &str = "data/shops.toml";
+
+#[derive(Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Stat {
+    Attack,
+    Defense,
+    Regen,
+}
+
+impl Stat {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Stat::Attack => "Attack",
+            Stat::Defense => "Defense",
+            Stat::Regen => "Regen",
+        }
+    }
+}
+
+#[derive(Clone)]
+pub enum Goods {
+    Item(Item),
+    // A permanent increase to one of the buyer's stats.
+    Upgrade(Stat, u16),
+}
+
+#[derive(Clone)]
+pub struct Ware {
+    pub goods: Goods,
+    pub price: u16,
+    // Unlimited when not set.
+    pub stock: Option<u32>,
+}
+
+impl Ware {
+    pub fn name(&self) -> String {
+        match self.goods {
+            Goods::Item(ref item) => item.name.clone(),
+            Goods::Upgrade(stat, amount) => format!("{} +{}", stat.name(), amount),
+        }
+    }
+
+    fn describe(&self) -> String {
+        let goods = match self.goods {
+            Goods::Item(ref item) => item.summary(),
+            Goods::Upgrade(..) => self.name(),
+        };
+
+        match self.stock {
+            Some(stock) => format!("{} - {} gold ({} left)", goods, self.price, stock),
+            None => format!("{} - {} gold", goods, self.price),
+        }
+    }
+}
+
+// A shopkeeper standing in a room. Stock runs down as it is sold and is
+// restored when the server restarts.
+#[derive(Clone)]
+pub struct Shop {
+    pub keeper: String,
+    pub description: String,
+    pub wares: Vec<Ware>,
+}
+
+impl Shop {
+    pub fn describe_wares(&self) -> String {
+        if self.wares.is_empty() {
+            return format!("{} has nothing for sale.", self.keeper);
+        }
+
+        let wares: Vec<String> = self.wares.iter().map(|ware| ware.describe()).collect();
+        format!("{} sells:\n{}", self.keeper, wares.join("\n"))
+    }
+
+    // Sells the named ware to the buyer, returning the shopkeeper's reply or
+    // why the sale fell through.
+    pub fn buy(&mut self, name: &str, buyer: &mut Entity) -> Result<String, String> {
+        let lower_name = name.to_lowercase();
+        let ware = match self
+            .wares
+            .iter_mut()
+            .find(|ware| ware.name().to_lowercase() == lower_name)
+        {
+            Some(ware) => ware,
+            None => return Err(format!("{} doesn't sell {}.", self.keeper, name)),
+        };
+
+        if ware.stock == Some(0) {
+            return Err(format!("{} is sold out.", ware.name()));
+        }
+
+        if buyer.gold < ware.price {
+            return Err(format!(
+                "{} costs {} gold, you only have {}.",
+                ware.name(),
+                ware.price,
+                buyer.gold
+            ));
+        }
+
+        buyer.gold -= ware.price;
+        if let Some(ref mut stock) = ware.stock {
+            *stock -= 1;
+        }
+
+        match ware.goods {
+            Goods::Item(ref item) => buyer.inventory.items.push(item.clone()),
+            Goods::Upgrade(stat, amount) => {
+                let value = match stat {
+                    Stat::Attack => &mut buyer.attack,
+                    Stat::Defense => &mut buyer.defense,
+                    Stat::Regen => &mut buyer.regen,
+                };
+                *value = value.saturating_add(amount);
+            }
+        }
+        buyer.update_dirty = true;
+
+        Ok(format!("You buy {} for {} gold.", ware.name(), ware.price))
+    }
+}
+
+// Commands sent as messages to a shopkeeper.
+pub enum ShopCommand {
+    List,
+    Buy(String),
+}
+
+const USAGE: &str = "Shop commands: /list, /buy <ware>";
+
+pub fn parse_shop_command(text: &str) -> Result<ShopCommand, String> {
+    let text = text.trim();
+    let (command, args) = match text.find(' ') {
+        Some(at) => (&text[..at], text[at..].trim()),
+        None => (text, ""),
+    };
+
+    match command {
+        "/list" => Ok(ShopCommand::List),
+        "/buy" if !args.is_empty() => Ok(ShopCommand::Buy(args.to_string())),
+        _ => Err(USAGE.to_string()),
+    }
+}
+
+#[derive(Debug)]
+pub enum ShopLoadError {
+    Io(String, String),
+    Parse(String),
+    UnknownItem(String, String),
+    BadWare(String),
+}
+
+impl fmt::Display for ShopLoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ShopLoadError::Io(ref path, ref reason) => {
+                write!(f, "Failed to read shop file '{}': {}", path, reason)
+            }
+            ShopLoadError::Parse(ref reason) => write!(f, "Invalid shop file: {}", reason),
+            ShopLoadError::UnknownItem(ref shop, ref item) => write!(
+                f,
+                "Shop '{}' sells item '{}', which is not defined.",
+                shop, item
+            ),
+            ShopLoadError::BadWare(ref shop) => write!(
+                f,
+                "Shop '{}' has a ware that must name either an item or a stat upgrade \
+                 with an amount above zero.",
+                shop
+            ),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct ShopFile {
+    #[serde(default)]
+    shops: HashMap<String, ShopEntry>,
+}
+
+#[derive(Deserialize)]
+struct ShopEntry {
+    keeper: String,
+    description: String,
+    #[serde(default)]
+    wares: Vec<WareEntry>,
+}
+
+#[derive(Deserialize)]
+struct WareEntry {
+    item: Option<String>,
+    upgrade: Option<Stat>,
+    #[serde(default)]
+    amount: u16,
+    price: u16,
+    stock: Option<u32>,
+}
+
+pub struct ShopCatalogue {
+    shops: HashMap<String, Shop>,
+}
+
+impl ShopCatalogue {
+    pub fn get(&self, id: &str) -> Option<&Shop> {
+        self.shops.get(id)
+    }
+}
+
+pub fn load_shops(path: &str, items: &ItemCatalogue) -> Result<ShopCatalogue, ShopLoadError> {
+    let mut contents = String::new();
+    File::open(path)
+        .and_then(|mut file| file.read_to_string(&mut contents))
+        .map_err(|e| ShopLoadError::Io(path.to_string(), e.to_string()))?;
+
+    parse_shops(&contents, items)
+}
+
+pub fn parse_shops(contents: &str, items: &ItemCatalogue) -> Result<ShopCatalogue, ShopLoadError> {
+    let shop_file: ShopFile =
+        toml::from_str(contents).map_err(|e| ShopLoadError::Parse(e.to_string()))?;
+
+    let mut shops = HashMap::new();
+    for (id, entry) in shop_file.shops.into_iter() {
+        let mut wares = vec![];
+        for ware in entry.wares.into_iter() {
+            let goods = match (ware.item, ware.upgrade) {
+                (Some(item), None) => Goods::Item(
+                    items
+                        .get(&item)
+                        .cloned()
+                        .ok_or_else(|| ShopLoadError::UnknownItem(id.clone(), item.clone()))?,
+                ),
+                (None, Some(stat)) if ware.amount > 0 => Goods::Upgrade(stat, ware.amount),
+                _ => return Err(ShopLoadError::BadWare(id)),
+            };
+
+            wares.push(Ware {
+                goods,
+                price: ware.price,
+                stock: ware.stock,
+            });
+        }
+
+        shops.insert(
+            id,
+            Shop {
+                keeper: entry.keeper,
+                description: entry.description,
+                wares,
+            },
+        );
+    }
+
+    Ok(ShopCatalogue { shops })
+}
@@ -0,0 +1,501 @@
+mod support;
+
+use std::time::Duration;
+use support::*;
+
+#[test]
+fn connecting_sends_game_description() {
+    let server = TestServer::start();
+    let mut client = server.connect();
+
+    match client.expect_game() {
+        Packet::Game { initial_points, .. } => assert_eq!(initial_points, 600),
+        _ => unreachable!(),
+    }
+}
+
+#[test]
+fn game_packet_reports_configured_values() {
+    let server = TestServer::start_with_args(&[
+        "--initial-points",
+        "300",
+        "--stat-limit",
+        "200",
+        "--default-health",
+        "250",
+    ]);
+    let mut client = server.connect();
+
+    match client.expect_game() {
+        Packet::Game {
+            initial_points,
+            stat_limit,
+            description,
+        } => {
+            assert_eq!((initial_points, stat_limit), (300, 200));
+            assert!(description.contains("250 health"));
+        }
+        _ => unreachable!(),
+    }
+
+    client.send_character("Greedy", true, 250, 50, 0, "Too strong.");
+    client.expect_error(ERROR_STAT_ERROR);
+}
+
+#[test]
+fn character_with_too_many_points_is_rejected() {
+    let server = TestServer::start();
+    let mut client = server.connect();
+    client.expect_game();
+
+    client.send_character("Greedy", true, 600, 600, 600, "Wants it all.");
+    client.expect_error(ERROR_STAT_ERROR);
+}
+
+#[test]
+fn starting_places_player_in_entry_room() {
+    let server = TestServer::start();
+    let mut client = LurkClient::ready(server.port(), "Alice", 200, 200, 200);
+
+    client.send_start();
+    assert_eq!(client.expect_room(), (1, "Entry Room".to_string()));
+    client.expect_connection("Parlor");
+    client.expect_character("Mean Butler");
+}
+
+#[test]
+fn actions_before_start_are_refused() {
+    let server = TestServer::start();
+    let mut client = LurkClient::ready(server.port(), "Eager", 200, 200, 200);
+
+    client.send_change_room(3);
+    client.expect_error(ERROR_NOT_READY);
+    client.send_fight();
+    client.expect_error(ERROR_NOT_READY);
+}
+
+#[test]
+fn change_room_only_moves_to_adjacent_rooms() {
+    let server = TestServer::start();
+    let mut client = LurkClient::started(server.port(), "Walker", 200, 200, 200);
+    let parlor = client.expect_connection("Parlor");
+
+    client.send_change_room(2);
+    client.expect_error(ERROR_BAD_ROOM);
+
+    client.send_change_room(parlor);
+    assert_eq!(client.expect_room(), (parlor, "Parlor".to_string()));
+    client.expect_connection("Entry Room");
+}
+
+#[test]
+fn fight_and_loot_the_butler() {
+    let server = TestServer::start();
+    let mut client = LurkClient::started(server.port(), "Brawler", 600, 0, 0);
+
+    client.send_loot("Mean Butler");
+    client.expect_error(ERROR_NO_TARGET);
+
+    // Brawler hits hard enough to finish the butler in a single round.
+    client.send_fight();
+    client.expect("dead butler", |packet| match *packet {
+        Packet::Character(ref info) => info.name == "Mean Butler" && !info.alive,
+        _ => false,
+    });
+    let (fight_text, _) = client.expect_message();
+    assert!(fight_text.contains("Mean Butler has fallen!"));
+
+    // The looted corpse is sent out of the world, and the gold shows up on
+    // the next update.
+    client.send_loot("Mean Butler");
+    client.expect("removed butler", |packet| match *packet {
+        Packet::Character(ref info) => info.name == "Mean Butler" && info.room == std::u16::MAX,
+        _ => false,
+    });
+    client.expect("richer brawler", |packet| match *packet {
+        Packet::Character(ref info) => info.name == "Brawler" && info.gold > 0,
+        _ => false,
+    });
+}
+
+#[test]
+fn messages_are_delivered_between_players() {
+    let server = TestServer::start();
+    let mut alice = LurkClient::started(server.port(), "Alice", 200, 200, 200);
+    let mut bob = LurkClient::started(server.port(), "Bob", 200, 200, 200);
+
+    alice.send_message("Bob", "Alice", "Hello there.");
+    assert_eq!(
+        bob.expect_message(),
+        ("Hello there.".to_string(), "Alice".to_string())
+    );
+
+    alice.send_message("Nobody", "Alice", "Anyone?");
+    alice.expect_error(ERROR_NO_TARGET);
+}
+
+#[test]
+fn pvp_is_refused_in_a_sanctuary() {
+    let server = TestServer::start_with_args(&["--pvp", "true"]);
+    let mut alice = LurkClient::started(server.port(), "Alice", 200, 200, 200);
+    let _bob = LurkClient::started(server.port(), "Bob", 200, 200, 200);
+
+    alice.send_pvp_fight("Bob");
+    alice.expect_error(ERROR_NO_PVP);
+}
+
+#[test]
+fn only_admins_can_command_the_server() {
+    // The admin's name is claimed before it is listed, as an operator would.
+    let mut server = TestServer::start();
+    let mut root = server.connect();
+    root.expect_game();
+    root.send_message("Server", "Root", "/password hunter2");
+    root.expect_message();
+    root.send_character("Root", true, 200, 200, 200, "The admin.");
+    root.expect_accept(CHARACTER_TYPE);
+    root.send_leave();
+    root.drain(Duration::from_millis(200));
+    server.restart_with_args(&["--admin", "Root"]);
+
+    let mut root = server.connect();
+    root.expect_game();
+    root.send_character("Root", true, 200, 200, 200, "The admin.");
+    root.expect_error(ERROR_OTHER);
+    root.send_message("Server", "Root", "/password hunter2");
+    root.expect_message();
+    root.send_character("Root", true, 200, 200, 200, "The admin.");
+    root.expect_accept(CHARACTER_TYPE);
+    root.send_start();
+    root.expect_room();
+
+    let mut bob = LurkClient::started(server.port(), "Bob", 200, 200, 200);
+
+    bob.send_message("Server", "Bob", "/give-gold Bob 100");
+    bob.expect_error(ERROR_OTHER);
+
+    root.send_message("Server", "Root", "/give-gold Bob 100");
+    assert_eq!(root.expect_message().0, "Gave 100 gold to Bob.");
+    bob.expect("richer bob", |packet| match *packet {
+        Packet::Character(ref info) => info.name == "Bob" && info.gold == 100,
+        _ => false,
+    });
+
+    root.send_message("Server", "Root", "/teleport Nobody 1");
+    root.expect_error(ERROR_NO_TARGET);
+
+    // A kicked client can't send its character again to get back in.
+    root.send_message("Server", "Root", "/kick Bob");
+    assert_eq!(root.expect_message().0, "Kicked Bob.");
+    bob.send_character("Bob", true, 200, 200, 200, "Back again.");
+    assert_eq!(
+        bob.expect_error(ERROR_OTHER),
+        "You have been removed from the game."
+    );
+}
+
+#[test]
+fn unclaimed_admin_names_cannot_be_taken() {
+    let server = TestServer::start_with_args(&["--admin", "Root"]);
+
+    let mut impostor = server.connect();
+    impostor.expect_game();
+    impostor.send_message("Server", "Root", "/password hunter2");
+    impostor.expect_message();
+    impostor.send_character("Root", true, 200, 200, 200, "The admin, honest.");
+    assert!(impostor
+        .expect_error(ERROR_PLAYER_EXISTS)
+        .contains("reserved for an administrator"));
+}
+
+#[test]
+fn room_chat_reaches_the_room_and_is_rate_limited() {
+    let server = TestServer::start_with_args(&["--room-chat-limit", "1", "--mute", "Carol"]);
+    let mut alice = LurkClient::started(server.port(), "Alice", 200, 200, 200);
+    let mut bob = LurkClient::started(server.port(), "Bob", 200, 200, 200);
+    let mut carol = LurkClient::started(server.port(), "Carol", 200, 200, 200);
+
+    alice.send_message("Room", "Alice", "Hello, room.");
+    assert_eq!(
+        bob.expect_message(),
+        ("Hello, room.".to_string(), "Alice".to_string())
+    );
+
+    alice.send_message("Room", "Alice", "Hello again.");
+    alice.expect_error(ERROR_OTHER);
+
+    carol.send_message("All", "Carol", "Can anyone hear me?");
+    carol.expect_error(ERROR_OTHER);
+}
+
+#[test]
+fn dead_players_can_be_looted_and_respawn() {
+    let server = TestServer::start_with_args(&[
+        "--pvp",
+        "true",
+        "--default-gold",
+        "100",
+        "--respawn-delay",
+        "2",
+        "--death-gold-penalty",
+        "50",
+    ]);
+    let mut alice = LurkClient::started(server.port(), "Alice", 600, 0, 0);
+    let mut bob = LurkClient::started(server.port(), "Bob", 0, 0, 0);
+
+    // The Entry Room is a sanctuary, so take the fight next door.
+    let parlor = alice.expect_connection("Parlor");
+    alice.send_change_room(parlor);
+    alice.expect_room();
+    bob.send_change_room(parlor);
+    bob.expect_room();
+
+    let mut bob_dead = false;
+    for _ in 0..20 {
+        alice.send_pvp_fight("Bob");
+        bob_dead = alice
+            .drain(Duration::from_millis(1500))
+            .iter()
+            .any(|packet| match *packet {
+                Packet::Character(ref info) => info.name == "Bob" && !info.alive,
+                _ => false,
+            });
+        if bob_dead {
+            break;
+        }
+    }
+    assert!(bob_dead, "Bob never died.");
+
+    alice.send_loot("Bob");
+    let (message, _) = alice.expect_message();
+    assert_eq!(message, "You took 100 gold from Bob.");
+    alice.expect("richer alice", |packet| match *packet {
+        Packet::Character(ref info) => info.name == "Alice" && info.gold == 200,
+        _ => false,
+    });
+
+    // Bob's gold went to Alice, so the penalty leaves him with nothing.
+    assert_eq!(bob.expect_room(), (1, "Entry Room".to_string()));
+    bob.expect("respawned bob", |packet| match *packet {
+        Packet::Character(ref info) => info.name == "Bob" && info.alive && info.gold == 0,
+        _ => false,
+    });
+}
+
+#[test]
+fn stat_points_can_only_be_spent_once_earned() {
+    let server = TestServer::start();
+    let mut client = LurkClient::started(server.port(), "Novice", 200, 200, 200);
+
+    // A fresh character has no points from leveling to spend, and stats can't
+    // be traded from one to another.
+    client.send_character("Novice", true, 210, 200, 200, "Wants more.");
+    client.expect_error(ERROR_STAT_ERROR);
+    client.send_character("Novice", true, 210, 190, 200, "Wants to swap.");
+    client.expect_error(ERROR_STAT_ERROR);
+
+    // Resending the character's stats as they are only changes the flags.
+    client.send_character("Novice", false, 200, 200, 200, "Sits this one out.");
+    client.expect_accept(CHARACTER_TYPE);
+    let info = client.expect_character("Novice");
+    assert!(!info.join_battle);
+    assert!(info.description.starts_with("[Level 1]"));
+}
+
+#[test]
+fn item_commands_work_for_every_player() {
+    let server = TestServer::start();
+    let mut client = LurkClient::started(server.port(), "Packrat", 200, 200, 200);
+
+    client.send_message("Server", "Packrat", "/inventory");
+    let (inventory, sender) = client.expect_message();
+    assert_eq!(sender, "Server");
+    assert!(inventory.contains("Carrying: nothing"));
+
+    client.send_message("Server", "Packrat", "/equip Silver Tray");
+    client.expect_error(ERROR_NO_TARGET);
+
+    // Anything that isn't an item command is still reserved for admins.
+    client.send_message("Server", "Packrat", "/give-gold Packrat 10");
+    client.expect_error(ERROR_OTHER);
+}
+
+#[test]
+fn shopkeepers_sell_wares_for_gold() {
+    let server = TestServer::start_with_args(&["--default-gold", "100"]);
+    let mut client = LurkClient::started(server.port(), "Shopper", 200, 200, 200);
+
+    let keeper = client.expect_character("Old Housekeeper");
+    assert!(keeper.alive);
+    assert!(!keeper.join_battle);
+
+    client.send_message("Old Housekeeper", "Shopper", "/buy Tea");
+    let (reply, sender) = client.expect_message();
+    assert_eq!(sender, "Old Housekeeper");
+    assert!(reply.contains("20 gold"));
+    assert_eq!(client.expect_character("Shopper").gold, 80);
+
+    client.send_message("Old Housekeeper", "Shopper", "/buy Silver Tray");
+    client.expect_error(ERROR_OTHER);
+
+    client.send_message("Server", "Shopper", "/inventory");
+    let (inventory, _) = client.expect_message();
+    assert!(inventory.contains("Tea"));
+}
+
+#[test]
+fn monsters_report_the_room_they_are_in() {
+    let server = TestServer::start();
+    let mut client = LurkClient::started(server.port(), "Surveyor", 200, 200, 200);
+
+    let butler = client.expect_character("Mean Butler");
+    assert!(butler.monster);
+    assert_eq!(butler.room, 1);
+}
+
+#[test]
+fn aggressive_monsters_attack_on_their_own() {
+    let server = TestServer::start_with_args(&["--monster-attack-interval", "1"]);
+    let mut client = LurkClient::started(server.port(), "Intruder", 600, 0, 0);
+
+    let parlor = client.expect_connection("Parlor");
+    client.send_change_room(parlor);
+    client.expect_room();
+    let basement = client.expect_connection("Basement");
+    client.send_change_room(basement);
+    client.expect_room();
+
+    // Derry attacks anyone who wanders into the basement.
+    client.expect("derry's attack", |packet| match *packet {
+        Packet::Message { ref message, .. } => message.contains("Derry"),
+        _ => false,
+    });
+}
+
+#[test]
+fn character_names_must_be_unique_and_unreserved() {
+    let server = TestServer::start();
+    let _alice = LurkClient::ready(server.port(), "Alice", 200, 200, 200);
+
+    let mut client = server.connect();
+    client.expect_game();
+
+    for name in ["alice", "Server", "All", "Mean Butler", "Small Spider 7"].iter() {
+        client.send_character(name, true, 200, 200, 200, "An impostor.");
+        client.expect_error(ERROR_PLAYER_EXISTS);
+    }
+
+    client.send_character("Bad!Name", true, 200, 200, 200, "Shouty.");
+    client.expect_error(ERROR_OTHER);
+
+    client.send_character("Alicia", true, 200, 200, 200, "Close enough.");
+    client.expect_accept(CHARACTER_TYPE);
+}
+
+#[test]
+fn claimed_names_need_their_password() {
+    let server = TestServer::start();
+
+    let mut owner = server.connect();
+    owner.expect_game();
+    owner.send_message("Server", "Hero", "/password hunter2");
+    owner.expect_message();
+    owner.send_character("Hero", true, 200, 200, 200, "The real one.");
+    owner.expect_accept(CHARACTER_TYPE);
+    owner.send_leave();
+    owner.drain(Duration::from_millis(200));
+
+    let mut thief = server.connect();
+    thief.expect_game();
+    thief.send_character("Hero", true, 200, 200, 200, "An impostor.");
+    thief.expect_error(ERROR_OTHER);
+
+    thief.send_message("Server", "Hero", "/password guess");
+    thief.send_character("Hero", true, 200, 200, 200, "An impostor.");
+    thief.expect_error(ERROR_OTHER);
+
+    thief.send_message("Server", "Hero", "/password hunter2");
+    thief.send_character("Hero", true, 200, 200, 200, "The real one.");
+    thief.expect_accept(CHARACTER_TYPE);
+}
+
+#[test]
+fn names_are_only_claimed_by_accepted_characters() {
+    let server = TestServer::start();
+
+    let mut squatter = server.connect();
+    squatter.expect_game();
+    squatter.send_message("Server", "Hero", "/password hunter2");
+    squatter.expect_message();
+    squatter.send_character("Hero", true, 600, 600, 600, "Wants it all.");
+    squatter.expect_error(ERROR_STAT_ERROR);
+    squatter.send_leave();
+    squatter.drain(Duration::from_millis(200));
+
+    let mut hero = server.connect();
+    hero.expect_game();
+    hero.send_character("Hero", true, 200, 200, 200, "Nobody's claim.");
+    hero.expect_accept(CHARACTER_TYPE);
+
+    // The password is kept for a corrected character on the same session.
+    let mut sidekick = server.connect();
+    sidekick.expect_game();
+    sidekick.send_message("Server", "Sidekick", "/password hunter2");
+    sidekick.expect_message();
+    sidekick.send_character("Sidekick", true, 600, 600, 600, "Wants it all.");
+    sidekick.expect_error(ERROR_STAT_ERROR);
+    sidekick.send_character("Sidekick", true, 200, 200, 200, "Settles down.");
+    sidekick.expect_accept(CHARACTER_TYPE);
+    sidekick.send_leave();
+    sidekick.drain(Duration::from_millis(200));
+
+    let mut impostor = server.connect();
+    impostor.expect_game();
+    impostor.send_character("Sidekick", true, 200, 200, 200, "An impostor.");
+    impostor.expect_error(ERROR_OTHER);
+}
+
+#[test]
+fn dropped_players_can_reconnect_to_their_character() {
+    let server = TestServer::start();
+    let mut flaky = LurkClient::started(server.port(), "Flaky", 200, 200, 200);
+    let parlor = flaky.expect_connection("Parlor");
+    flaky.send_change_room(parlor);
+    flaky.expect_room();
+    drop(flaky);
+
+    let mut watcher = LurkClient::started(server.port(), "Watcher", 200, 200, 200);
+    watcher.send_change_room(parlor);
+    watcher.expect_room();
+    let info = watcher.expect_character("Flaky");
+    assert!(info.description.contains("[Linkdead]"));
+
+    let mut flaky = server.connect();
+    flaky.expect_game();
+    flaky.send_character("Flaky", true, 200, 200, 200, "A test character.");
+    flaky.expect_accept(CHARACTER_TYPE);
+    let info = flaky.expect_character("Flaky");
+    assert!(info.started);
+    assert_eq!(info.room, parlor);
+    assert_eq!(flaky.expect_room(), (parlor, "Parlor".to_string()));
+    flaky.expect_connection("Entry Room");
+    flaky.expect_character("Watcher");
+}
+
+#[test]
+fn idle_players_go_afk_and_are_then_removed() {
+    let server = TestServer::start_with_args(&["--afk-timeout", "1", "--idle-timeout", "3"]);
+    let mut idler = LurkClient::started(server.port(), "Idler", 200, 200, 200);
+
+    let (message, _) = idler.expect_message();
+    assert!(message.contains("AFK"));
+    let info = idler.expect_character("Idler");
+    assert!(info.description.contains("[AFK]"));
+
+    let (message, _) = idler.expect_message();
+    assert!(message.contains("idle"));
+
+    // The session can pick the saved character back up.
+    idler.send_character("Idler", true, 200, 200, 200, "A test character.");
+    idler.expect_accept(CHARACTER_TYPE);
+}
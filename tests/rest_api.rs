@@ -0,0 +1,177 @@
+mod support;
+
+use std::time::Duration;
+use support::*;
+
+fn start_with_rest() -> (TestServer, u16) {
+    let rest_port = free_port();
+    let rest_addr = format!("127.0.0.1:{}", rest_port);
+    (
+        TestServer::start_with_args(&["--rest", &rest_addr]),
+        rest_port,
+    )
+}
+
+#[test]
+fn rooms_are_reported_as_json() {
+    let (_server, rest_port) = start_with_rest();
+
+    let (status, body) = http_get(rest_port, "/api/rooms/1");
+    assert_eq!(status, 200);
+    assert!(body.contains("\"name\":\"Entry Room\""));
+    assert!(body.contains("\"name\":\"Mean Butler\""));
+
+    let (status, _) = http_get(rest_port, "/api/rooms/9999");
+    assert_eq!(status, 404);
+}
+
+#[test]
+fn started_players_are_reported_with_their_location() {
+    let (server, rest_port) = start_with_rest();
+    let _client = LurkClient::started(server.port(), "Alice", 200, 200, 200);
+
+    let (status, body) = http_get(rest_port, "/api/players");
+    assert_eq!(status, 200);
+    assert!(body.contains("\"name\":\"Alice\""));
+    assert!(body.contains("\"location\":{\"number\":1,\"name\":\"Entry Room\"}"));
+}
+
+#[test]
+fn players_can_be_looked_up_by_id() {
+    let (server, rest_port) = start_with_rest();
+    let _client = LurkClient::started(server.port(), "Alice", 200, 200, 200);
+
+    let (_, body) = http_get(rest_port, "/api/players");
+    let start = body.find("\"id\":\"").expect("No player id listed.") + 6;
+    let id = &body[start..start + 36];
+
+    let (status, body) = http_get(rest_port, &format!("/api/players/{}", id));
+    assert_eq!(status, 200);
+    assert!(body.contains("\"name\":\"Alice\""));
+
+    let (status, _) = http_get(rest_port, "/api/players/not-an-id");
+    assert_eq!(status, 400);
+
+    let (status, _) = http_get(
+        rest_port,
+        "/api/players/00000000-0000-0000-0000-000000000000",
+    );
+    assert_eq!(status, 404);
+}
+
+#[test]
+fn all_rooms_are_listed_in_order() {
+    let (_server, rest_port) = start_with_rest();
+
+    let (status, body) = http_get(rest_port, "/api/rooms");
+    assert_eq!(status, 200);
+    assert!(body.starts_with("[{\"number\":1,\"name\":\"Entry Room\""));
+    assert!(body.contains("\"name\":\"Parlor\""));
+
+    let (status, _) = http_get(rest_port, "/api/rooms/basement");
+    assert_eq!(status, 400);
+}
+
+#[test]
+fn admins_can_reset_a_locked_out_account() {
+    let rest_port = free_port();
+    let rest_addr = format!("127.0.0.1:{}", rest_port);
+    let server = TestServer::start_with_args(&[
+        "--rest",
+        &rest_addr,
+        "--rest-admin-token",
+        "secret",
+        "--max-login-failures",
+        "1",
+    ]);
+
+    let mut owner = server.connect();
+    owner.expect_game();
+    owner.send_message("Server", "Forgetful", "/password first");
+    owner.send_character("Forgetful", true, 200, 200, 200, "Can't remember.");
+    owner.expect_accept(CHARACTER_TYPE);
+    owner.send_leave();
+    owner.drain(Duration::from_millis(200));
+
+    let mut client = server.connect();
+    client.expect_game();
+    client.send_message("Server", "Forgetful", "/password second");
+    client.send_character("Forgetful", true, 200, 200, 200, "Can't remember.");
+    client.expect_error(ERROR_OTHER);
+
+    let (status, _) = http_post(rest_port, "/api/accounts/Forgetful/reset", None);
+    assert_eq!(status, 401);
+    let (status, body) = http_post(rest_port, "/api/accounts/Forgetful/reset", Some("secret"));
+    assert_eq!(status, 200);
+    assert!(body.contains("\"name\":\"Forgetful\""));
+
+    client.send_message("Server", "Forgetful", "/password second");
+    client.send_character("Forgetful", true, 200, 200, 200, "Can't remember.");
+    client.expect_accept(CHARACTER_TYPE);
+}
+
+#[test]
+fn flooding_clients_are_limited_dropped_and_counted() {
+    let rest_port = free_port();
+    let rest_addr = format!("127.0.0.1:{}", rest_port);
+    let server = TestServer::start_with_args(&[
+        "--rest",
+        &rest_addr,
+        "--fight-burst",
+        "2",
+        "--fight-rate",
+        "0.1",
+        "--rate-limit-strikes",
+        "3",
+    ]);
+    let mut client = LurkClient::started(server.port(), "Spammer", 200, 200, 200);
+
+    for _ in 0..5 {
+        client.send_fight();
+    }
+    client.expect("rate limit error", |packet| match *packet {
+        Packet::Error { ref message, .. } => message.contains("too quickly"),
+        _ => false,
+    });
+    client.expect("flooding error", |packet| match *packet {
+        Packet::Error { ref message, .. } => message.contains("flooding"),
+        _ => false,
+    });
+
+    // A dropped client can't make a new character to get back in.
+    client.send_character("Spammer", true, 200, 200, 200, "Back again.");
+    assert_eq!(
+        client.expect_error(ERROR_OTHER),
+        "You have been removed from the game."
+    );
+    client.send_start();
+    assert_eq!(
+        client.expect_error(ERROR_OTHER),
+        "You have been removed from the game."
+    );
+
+    let (status, body) = http_get(rest_port, "/api/limits");
+    assert_eq!(status, 200);
+    assert!(body.contains("\"fight\":{\"allowed\":2,\"limited\":3}"));
+    assert!(body.contains("\"dropped\":1"));
+}
+
+#[test]
+fn the_same_seed_spawns_the_same_monsters() {
+    let rooms_with_seed = |seed: &str| {
+        let rest_port = free_port();
+        let rest_addr = format!("127.0.0.1:{}", rest_port);
+        // No updates run, so nothing moves or respawns before the report.
+        let _server = TestServer::start_with_args(&[
+            "--rest",
+            &rest_addr,
+            "--seed",
+            seed,
+            "--tick-interval",
+            "600000",
+        ]);
+        http_get(rest_port, "/api/rooms").1
+    };
+
+    assert_eq!(rooms_with_seed("42"), rooms_with_seed("42"));
+}
@@ -0,0 +1,557 @@
+// A minimal Lurk client and a handle on a server process, for driving the
+// server end to end from tests.
+
+#![allow(dead_code)]
+
+use std::env;
+use std::fs;
+use std::io::{ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+pub const MESSAGE_TYPE: u8 = 1;
+pub const CHANGE_ROOM_TYPE: u8 = 2;
+pub const FIGHT_TYPE: u8 = 3;
+pub const PVP_FIGHT_TYPE: u8 = 4;
+pub const LOOT_TYPE: u8 = 5;
+pub const START_TYPE: u8 = 6;
+pub const ERROR_TYPE: u8 = 7;
+pub const ACCEPT_TYPE: u8 = 8;
+pub const ROOM_TYPE: u8 = 9;
+pub const CHARACTER_TYPE: u8 = 10;
+pub const GAME_TYPE: u8 = 11;
+pub const LEAVE_TYPE: u8 = 12;
+pub const CONNECTION_TYPE: u8 = 13;
+
+pub const ERROR_OTHER: u8 = 0;
+pub const ERROR_BAD_ROOM: u8 = 1;
+pub const ERROR_PLAYER_EXISTS: u8 = 2;
+pub const ERROR_STAT_ERROR: u8 = 4;
+pub const ERROR_NOT_READY: u8 = 5;
+pub const ERROR_NO_TARGET: u8 = 6;
+pub const ERROR_NO_PVP: u8 = 8;
+
+const NAME_LEN: usize = 32;
+
+const ALIVE_FLAG: u8 = 0x80;
+const JOIN_BATTLE_FLAG: u8 = 0x40;
+const MONSTER_FLAG: u8 = 0x20;
+const STARTED_FLAG: u8 = 0x10;
+const READY_FLAG: u8 = 0x08;
+
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+static SERVER_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+#[derive(Clone, Debug)]
+pub struct CharacterInfo {
+    pub name: String,
+    pub alive: bool,
+    pub join_battle: bool,
+    pub monster: bool,
+    pub started: bool,
+    pub ready: bool,
+    pub attack: u16,
+    pub defense: u16,
+    pub regen: u16,
+    pub health: i16,
+    pub gold: u16,
+    pub room: u16,
+    pub description: String,
+}
+
+#[derive(Clone, Debug)]
+pub enum Packet {
+    Message {
+        message: String,
+        recipient: String,
+        sender: String,
+    },
+    Error {
+        code: u8,
+        message: String,
+    },
+    Accept {
+        accepted: u8,
+    },
+    Room {
+        number: u16,
+        name: String,
+        description: String,
+    },
+    Character(CharacterInfo),
+    Game {
+        initial_points: u16,
+        stat_limit: u16,
+        description: String,
+    },
+    Connection {
+        number: u16,
+        name: String,
+        description: String,
+    },
+}
+
+// A server process listening on a free local port with its own character
+// store. The process is killed when this is dropped.
+pub struct TestServer {
+    process: Child,
+    port: u16,
+    store_dir: PathBuf,
+}
+
+impl TestServer {
+    pub fn start() -> TestServer {
+        TestServer::start_with_args(&[])
+    }
+
+    pub fn start_with_args(extra_args: &[&str]) -> TestServer {
+        let port = free_port();
+
+        let store_dir = env::temp_dir().join(format!(
+            "lurk-test-{}-{}",
+            std::process::id(),
+            SERVER_COUNT.fetch_add(1, Ordering::SeqCst)
+        ));
+        let _ = fs::remove_dir_all(&store_dir);
+
+        TestServer {
+            process: launch(port, &store_dir, extra_args),
+            port,
+            store_dir,
+        }
+    }
+
+    // Starts the server again with new arguments, keeping its saved
+    // characters and accounts.
+    pub fn restart_with_args(&mut self, extra_args: &[&str]) {
+        let _ = self.process.kill();
+        let _ = self.process.wait();
+
+        self.port = free_port();
+        self.process = launch(self.port, &self.store_dir, extra_args);
+    }
+
+    pub fn connect(&self) -> LurkClient {
+        LurkClient::connect(self.port)
+    }
+
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+}
+
+fn launch(port: u16, store_dir: &Path, extra_args: &[&str]) -> Child {
+    let mut process = Command::new(env!("CARGO_BIN_EXE_liblurk-rs_server_example"))
+        .arg("--address")
+        .arg(format!("127.0.0.1:{}", port))
+        .arg("--seed")
+        .arg("1")
+        .arg("--store")
+        .arg(store_dir.join("characters.json"))
+        .arg("--accounts")
+        .arg(store_dir.join("accounts.json"))
+        .args(extra_args)
+        .stdout(Stdio::null())
+        .spawn()
+        .expect("Failed to launch server.");
+
+    // Wait for the server to start accepting connections.
+    let deadline = Instant::now() + READ_TIMEOUT;
+    while TcpStream::connect(("127.0.0.1", port)).is_err() {
+        if Instant::now() > deadline {
+            let _ = process.kill();
+            panic!("Server never started listening.");
+        }
+        thread::sleep(Duration::from_millis(50));
+    }
+
+    process
+}
+
+impl Drop for TestServer {
+    fn drop(&mut self) {
+        let _ = self.process.kill();
+        let _ = self.process.wait();
+        let _ = fs::remove_dir_all(&self.store_dir);
+    }
+}
+
+pub fn free_port() -> u16 {
+    let listener = TcpListener::bind("127.0.0.1:0").expect("No free port.");
+    listener.local_addr().unwrap().port()
+}
+
+// Issues a plain HTTP/1.0 GET and returns the status code and body.
+pub fn http_get(port: u16, path: &str) -> (u16, String) {
+    http_request(port, "GET", path, "")
+}
+
+// As http_get, but a POST sending the token as a bearer token if given.
+pub fn http_post(port: u16, path: &str, token: Option<&str>) -> (u16, String) {
+    let headers = match token {
+        Some(token) => format!("Authorization: Bearer {}\r\nContent-Length: 0\r\n", token),
+        None => "Content-Length: 0\r\n".to_string(),
+    };
+    http_request(port, "POST", path, &headers)
+}
+
+fn http_request(port: u16, method: &str, path: &str, headers: &str) -> (u16, String) {
+    let mut stream = TcpStream::connect(("127.0.0.1", port)).expect("Failed to connect.");
+    stream.set_read_timeout(Some(READ_TIMEOUT)).unwrap();
+    write!(
+        stream,
+        "{} {} HTTP/1.0\r\nHost: localhost\r\n{}\r\n",
+        method, path, headers
+    )
+    .unwrap();
+
+    let mut response = String::new();
+    stream
+        .read_to_string(&mut response)
+        .expect("Failed to read response.");
+
+    let status = response
+        .split(' ')
+        .nth(1)
+        .and_then(|code| code.parse().ok())
+        .expect("Malformed status line.");
+    let body = match response.find("\r\n\r\n") {
+        Some(at) => response[at + 4..].to_string(),
+        None => String::new(),
+    };
+
+    (status, body)
+}
+
+pub struct LurkClient {
+    stream: TcpStream,
+    buffer: Vec<u8>,
+}
+
+impl LurkClient {
+    pub fn connect(port: u16) -> LurkClient {
+        let stream = TcpStream::connect(("127.0.0.1", port)).expect("Failed to connect.");
+        stream
+            .set_read_timeout(Some(Duration::from_millis(100)))
+            .unwrap();
+        LurkClient {
+            stream,
+            buffer: vec![],
+        }
+    }
+
+    // Connects and creates a ready character with the given stats.
+    pub fn ready(port: u16, name: &str, attack: u16, defense: u16, regen: u16) -> LurkClient {
+        let mut client = LurkClient::connect(port);
+        client.expect_game();
+        client.send_character(name, true, attack, defense, regen, "A test character.");
+        client.expect_accept(CHARACTER_TYPE);
+        client.expect_character(name);
+        client
+    }
+
+    // As ready, then starts the game.
+    pub fn started(port: u16, name: &str, attack: u16, defense: u16, regen: u16) -> LurkClient {
+        let mut client = LurkClient::ready(port, name, attack, defense, regen);
+        client.send_start();
+        client.expect_room();
+        client
+    }
+
+    pub fn send_message(&mut self, recipient: &str, sender: &str, message: &str) {
+        let mut packet = vec![MESSAGE_TYPE];
+        push_u16(&mut packet, message.len() as u16);
+        push_name(&mut packet, recipient);
+        push_name(&mut packet, sender);
+        packet.extend_from_slice(message.as_bytes());
+        self.send(&packet);
+    }
+
+    pub fn send_change_room(&mut self, room: u16) {
+        let mut packet = vec![CHANGE_ROOM_TYPE];
+        push_u16(&mut packet, room);
+        self.send(&packet);
+    }
+
+    pub fn send_fight(&mut self) {
+        self.send(&[FIGHT_TYPE]);
+    }
+
+    pub fn send_pvp_fight(&mut self, target: &str) {
+        let mut packet = vec![PVP_FIGHT_TYPE];
+        push_name(&mut packet, target);
+        self.send(&packet);
+    }
+
+    pub fn send_loot(&mut self, target: &str) {
+        let mut packet = vec![LOOT_TYPE];
+        push_name(&mut packet, target);
+        self.send(&packet);
+    }
+
+    pub fn send_start(&mut self) {
+        self.send(&[START_TYPE]);
+    }
+
+    pub fn send_leave(&mut self) {
+        self.send(&[LEAVE_TYPE]);
+    }
+
+    pub fn send_character(
+        &mut self,
+        name: &str,
+        join_battle: bool,
+        attack: u16,
+        defense: u16,
+        regen: u16,
+        description: &str,
+    ) {
+        let mut packet = vec![CHARACTER_TYPE];
+        push_name(&mut packet, name);
+        packet.push(if join_battle { JOIN_BATTLE_FLAG } else { 0 });
+        push_u16(&mut packet, attack);
+        push_u16(&mut packet, defense);
+        push_u16(&mut packet, regen);
+        push_u16(&mut packet, 0);
+        push_u16(&mut packet, 0);
+        push_u16(&mut packet, 0);
+        push_u16(&mut packet, description.len() as u16);
+        packet.extend_from_slice(description.as_bytes());
+        self.send(&packet);
+    }
+
+    fn send(&mut self, packet: &[u8]) {
+        self.stream
+            .write_all(packet)
+            .expect("Failed to send packet.");
+    }
+
+    // Reads packets until one matches, failing the test after a timeout.
+    pub fn expect<F: Fn(&Packet) -> bool>(&mut self, what: &str, matches: F) -> Packet {
+        let deadline = Instant::now() + READ_TIMEOUT;
+        let mut seen = vec![];
+        loop {
+            if let Some(packet) = self.read_packet(deadline) {
+                if matches(&packet) {
+                    return packet;
+                }
+                seen.push(packet);
+            } else {
+                panic!("Timed out waiting for {}. Received: {:?}", what, seen);
+            }
+        }
+    }
+
+    pub fn expect_game(&mut self) -> Packet {
+        self.expect("game", |packet| match *packet {
+            Packet::Game { .. } => true,
+            _ => false,
+        })
+    }
+
+    pub fn expect_accept(&mut self, accepted_type: u8) {
+        self.expect("accept", |packet| match *packet {
+            Packet::Accept { accepted } => accepted == accepted_type,
+            _ => false,
+        });
+    }
+
+    pub fn expect_error(&mut self, error_code: u8) -> String {
+        match self.expect("error", |packet| match *packet {
+            Packet::Error { code, .. } => code == error_code,
+            _ => false,
+        }) {
+            Packet::Error { message, .. } => message,
+            _ => unreachable!(),
+        }
+    }
+
+    pub fn expect_room(&mut self) -> (u16, String) {
+        match self.expect("room", |packet| match *packet {
+            Packet::Room { .. } => true,
+            _ => false,
+        }) {
+            Packet::Room { number, name, .. } => (number, name),
+            _ => unreachable!(),
+        }
+    }
+
+    pub fn expect_connection(&mut self, connection_name: &str) -> u16 {
+        match self.expect("connection", |packet| match *packet {
+            Packet::Connection { ref name, .. } => name == connection_name,
+            _ => false,
+        }) {
+            Packet::Connection { number, .. } => number,
+            _ => unreachable!(),
+        }
+    }
+
+    pub fn expect_character(&mut self, character_name: &str) -> CharacterInfo {
+        match self.expect("character", |packet| match *packet {
+            Packet::Character(ref info) => info.name == character_name,
+            _ => false,
+        }) {
+            Packet::Character(info) => info,
+            _ => unreachable!(),
+        }
+    }
+
+    pub fn expect_message(&mut self) -> (String, String) {
+        match self.expect("message", |packet| match *packet {
+            Packet::Message { .. } => true,
+            _ => false,
+        }) {
+            Packet::Message {
+                message, sender, ..
+            } => (message, sender),
+            _ => unreachable!(),
+        }
+    }
+
+    // Collects whatever arrives within the given time.
+    pub fn drain(&mut self, duration: Duration) -> Vec<Packet> {
+        let deadline = Instant::now() + duration;
+        let mut packets = vec![];
+        while let Some(packet) = self.read_packet(deadline) {
+            packets.push(packet);
+        }
+        packets
+    }
+
+    fn read_packet(&mut self, deadline: Instant) -> Option<Packet> {
+        loop {
+            if let Some((packet, len)) = parse_packet(&self.buffer) {
+                self.buffer.drain(..len);
+                return Some(packet);
+            }
+
+            if Instant::now() > deadline {
+                return None;
+            }
+
+            let mut chunk = [0u8; 4096];
+            match self.stream.read(&mut chunk) {
+                Ok(0) => return None,
+                Ok(n) => self.buffer.extend_from_slice(&chunk[..n]),
+                Err(ref e)
+                    if e.kind() == ErrorKind::WouldBlock || e.kind() == ErrorKind::TimedOut => {}
+                Err(e) => panic!("Failed to read from server: {}", e),
+            }
+        }
+    }
+}
+
+fn push_u16(packet: &mut Vec<u8>, value: u16) {
+    packet.push(value as u8);
+    packet.push((value >> 8) as u8);
+}
+
+fn push_name(packet: &mut Vec<u8>, name: &str) {
+    let mut bytes = [0u8; NAME_LEN];
+    for (i, byte) in name.bytes().take(NAME_LEN).enumerate() {
+        bytes[i] = byte;
+    }
+    packet.extend_from_slice(&bytes);
+}
+
+fn read_u16(bytes: &[u8], at: usize) -> u16 {
+    bytes[at] as u16 | (bytes[at + 1] as u16) << 8
+}
+
+fn read_name(bytes: &[u8], at: usize) -> String {
+    let name = &bytes[at..at + NAME_LEN];
+    let end = name.iter().position(|&b| b == 0).unwrap_or(NAME_LEN);
+    String::from_utf8_lossy(&name[..end]).into_owned()
+}
+
+fn read_text(bytes: &[u8], at: usize, len: usize) -> String {
+    String::from_utf8_lossy(&bytes[at..at + len]).into_owned()
+}
+
+// Returns the packet at the front of the buffer and its length, if a whole
+// one has arrived.
+fn parse_packet(bytes: &[u8]) -> Option<(Packet, usize)> {
+    if bytes.is_empty() {
+        return None;
+    }
+
+    // Length of the fixed part after the type byte, and where in it the
+    // variable length text's size is stored.
+    let (fixed_len, text_len_at) = match bytes[0] {
+        MESSAGE_TYPE => (2 + 2 * NAME_LEN, Some(0)),
+        ERROR_TYPE => (3, Some(1)),
+        ACCEPT_TYPE => (1, None),
+        ROOM_TYPE | CONNECTION_TYPE => (2 + NAME_LEN + 2, Some(2 + NAME_LEN)),
+        CHARACTER_TYPE => (NAME_LEN + 15, Some(NAME_LEN + 13)),
+        GAME_TYPE => (6, Some(4)),
+        other => panic!("Unexpected packet type {} from server.", other),
+    };
+
+    if bytes.len() < 1 + fixed_len {
+        return None;
+    }
+
+    let text_len = text_len_at.map_or(0, |at| read_u16(bytes, 1 + at) as usize);
+    let total_len = 1 + fixed_len + text_len;
+    if bytes.len() < total_len {
+        return None;
+    }
+
+    let body = &bytes[1..];
+    let text = read_text(body, fixed_len, text_len);
+
+    let packet = match bytes[0] {
+        MESSAGE_TYPE => Packet::Message {
+            message: text,
+            recipient: read_name(body, 2),
+            sender: read_name(body, 2 + NAME_LEN),
+        },
+        ERROR_TYPE => Packet::Error {
+            code: body[0],
+            message: text,
+        },
+        ACCEPT_TYPE => Packet::Accept { accepted: body[0] },
+        ROOM_TYPE => Packet::Room {
+            number: read_u16(body, 0),
+            name: read_name(body, 2),
+            description: text,
+        },
+        CONNECTION_TYPE => Packet::Connection {
+            number: read_u16(body, 0),
+            name: read_name(body, 2),
+            description: text,
+        },
+        CHARACTER_TYPE => {
+            let flags = body[NAME_LEN];
+            let stats_at = NAME_LEN + 1;
+            Packet::Character(CharacterInfo {
+                name: read_name(body, 0),
+                alive: flags & ALIVE_FLAG != 0,
+                join_battle: flags & JOIN_BATTLE_FLAG != 0,
+                monster: flags & MONSTER_FLAG != 0,
+                started: flags & STARTED_FLAG != 0,
+                ready: flags & READY_FLAG != 0,
+                attack: read_u16(body, stats_at),
+                defense: read_u16(body, stats_at + 2),
+                regen: read_u16(body, stats_at + 4),
+                health: read_u16(body, stats_at + 6) as i16,
+                gold: read_u16(body, stats_at + 8),
+                room: read_u16(body, stats_at + 10),
+                description: text,
+            })
+        }
+        GAME_TYPE => Packet::Game {
+            initial_points: read_u16(body, 0),
+            stat_limit: read_u16(body, 2),
+            description: text,
+        },
+        _ => unreachable!(),
+    };
+
+    Some((packet, total_len))
+}